@@ -1,180 +1,1178 @@
+use std::fmt;
+
+#[derive(Clone, Default, Debug)]
+pub struct Location {
+    pub file : String,
+    pub line : usize,
+    pub column : usize
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i32),
+    Float(f64)
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::Float(n) => write!(f, "{}", n)
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum TokenKind {
+    Indent,
+    Word(String),
+    Str(String),
+    Number(Number, String),
+    Sigil(char),
+    Key(String),
+    Doc(String)
+}
+
+#[derive(Clone)]
+pub struct Token {
+    pub kind : TokenKind,
+    pub loc : Location
+}
+
+impl Token {
+    pub fn text(&self) -> String {
+        match &self.kind {
+            TokenKind::Indent => "\t".to_owned(),
+            TokenKind::Word(s) | TokenKind::Str(s) | TokenKind::Doc(s) => s.clone(),
+            TokenKind::Number(_, t) => t.clone(),
+            TokenKind::Sigil(c) => c.to_string(),
+            TokenKind::Key(k) => k.clone() + "="
+        }
+    }
+}
+
+//Argument of a statement, references ('$NAME', '!NAME' or '&NAME') are resolved when the call runs
+#[derive(Clone)]
+pub enum Term {
+    Word(String),
+    Str(String),
+    Number(Number, String),
+    Ref(String),
+    Proc(String),
+    Key(String, Box<Term>)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CallMode {
+    Plain,
+    Forced,
+    Variable
+}
+
+#[derive(Clone)]
+pub struct Call {
+    pub mode : CallMode,
+    pub name : String,
+    pub args : Vec<Term>
+}
+
+#[derive(Clone)]
+pub enum Statement {
+    Call(Call),
+    Tag(String, Vec<Term>),
+    Row(Vec<Term>),
+    Invalid
+}
+
 #[derive(Clone)]
 pub struct Block {
     pub subs : Vec<Block>,
-    pub data : Vec<String>
+    pub stmt : Statement,
+    pub loc : Location,
+    pub doc : Vec<String>
+}
+
+//Named parameter of a PROC, a variadic one collects the remaining arguments as a list
+#[derive(Clone)]
+pub struct Param {
+    pub name : String,
+    pub variadic : bool,
+    pub default : Option<Term>
 }
 
 #[derive(Clone)]
 pub struct Proc {
     pub name : String,
-    pub mems : Vec<Block>
+    pub params : Vec<Param>,
+    pub mems : Vec<Block>,
+    pub doc : Vec<String>,
+    pub namespace : String,
+    pub loc : Location
+}
+
+//Item of the WORKING-STORAGE section, globals can be changed by any proc but constants can't
+#[derive(Clone)]
+pub struct StorageItem {
+    pub name : String,
+    pub value : Term,
+    pub constant : bool,
+    pub loc : Location
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Word(w) => write!(f, "{}", w),
+            Term::Str(s) => write!(f, "{:?}", s),
+            Term::Number(_, t) => write!(f, "{}", t),
+            Term::Ref(r) => write!(f, "${}", r),
+            Term::Proc(p) => write!(f, "&{}", p),
+            Term::Key(k, v) => write!(f, "{}={}", k, v)
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match &self.default {
+            Some(d) => write!(f, "{}={}", self.name, d),
+            None => write!(f, "{}{}", self.name, if self.variadic {"..."} else {""})
+        }
+    }
+}
+
+//Header of the proc as it is declared, followed by its doc comment
+impl fmt::Display for Proc {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PROC {}", self.name)?;
+        for p in self.params.iter() {
+            write!(f, " {}", p)?;
+        }
+        for d in self.doc.iter() {
+            write!(f, "\n\t{}", d)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
 pub struct ProgramInstance {
     pub name : String,
     pub entry_point : String,
-    pub methods : Vec<Proc>
+    pub storage : Vec<StorageItem>,
+    pub methods : Vec<Proc>,
+    pub max_depth : usize,
+    pub stack_size : usize
 }
 
 pub mod lexer {
 
     use std::io::{Read, BufRead, BufReader, Error, ErrorKind};
+    use std::convert::TryFrom;
+    use crate::core::{Token, TokenKind, Location, Number};
+    use crate::core::error::{located, Diagnostics};
+
+    //Text block opened by a trailing '"""', it captures the deeper indented lines verbatim
+    struct TextBlock {
+        depth : usize,
+        loc : Location,
+        lines : Vec<String>
+    }
+
+    impl TextBlock {
+        fn token(mut self) -> Token {
+            while let Some(l) = self.lines.last() {
+                if !l.is_empty() {
+                    break;
+                }
+                self.lines.pop();
+            }
+            return Token{kind:TokenKind::Str(self.lines.join("\n")), loc:self.loc};
+        }
+    }
+
+    //A TRAILING """ OPENS A TEXT BLOCK ONLY WHEN IT IS A TOKEN OF ITS OWN, NOT INSIDE A COMMENT OR A # LITERAL
+    fn text_opening(ln : &str, base : &Location, comment : &Option<Location>) -> Option<String> {
+        let t = ln.trim_end();
+        if !t.ends_with("\"\"\"") {
+            return None;
+        }
+        let head = &t[..t.len()-3];
+        if !(head.is_empty() || head.ends_with(' ') || head.ends_with('\t')) {
+            return None;
+        }
+        //PUT AN EMPTY STRING IN ITS PLACE, IT ONLY COMES BACK IF THE LEXER READS IT AS ONE
+        let column = base.column + head.chars().count();
+        let probe = line_tokens(format!("{}\"\"", head), base, &mut comment.clone()).ok()?;
+        match probe.last() {
+            Some(Token{kind:TokenKind::Str(s), loc}) if s.is_empty() && loc.column == column => Some(head.to_owned()),
+            _ => None
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum Indent {
+        Tabs,
+        Spaces(usize)
+    }
+
+    impl Indent {
+        fn unit(&self) -> char {
+            match self {
+                Indent::Tabs => '\t',
+                Indent::Spaces(_) => ' '
+            }
+        }
+
+        fn width(&self) -> usize {
+            match self {
+                Indent::Tabs => 1,
+                Indent::Spaces(n) => *n
+            }
+        }
+    }
+
+    fn unit_name(c : char) -> &'static str {
+        if c == '\t' {"tabs"} else {"spaces"}
+    }
+
+    //Splits the leading indentation of the line, returning its level and the rest of the line
+    fn split_indent(ln : &str, indent : Indent, loc : &Location, first : &mut Option<(char, usize)>) -> Result<(usize, String), Error> {
+        let prefix : String = ln.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        let body : String = ln.chars().skip(prefix.chars().count()).collect();
+        if prefix.is_empty() || body.is_empty() || body.starts_with(';') {
+            return Ok((0, body));
+        }
+        if prefix.contains(' ') && prefix.contains('\t') {
+            return Err(located(Error::new(ErrorKind::InvalidData,
+                format!("Mixed tabs and spaces in the indentation of line {}", loc.line)), loc));
+        }
+        let used = prefix.chars().next().unwrap();
+        if used != indent.unit() {
+            let msg = match first {
+                Some((c, l)) => format!("Line {} is indented with {} but line {} is indented with {}",
+                    loc.line, unit_name(used), l, unit_name(*c)),
+                None => format!("Line {} is indented with {} but the indentation unit is {}",
+                    loc.line, unit_name(used), unit_name(indent.unit()))
+            };
+            return Err(located(Error::new(ErrorKind::InvalidData, msg), loc));
+        }
+        if first.is_none() {
+            *first = Some((used, loc.line));
+        }
+        let n = prefix.chars().count();
+        if n % indent.width() != 0 {
+            return Err(located(Error::new(ErrorKind::InvalidData,
+                format!("Indentation of {} spaces at line {} is not a multiple of {}", n, loc.line, indent.width())), loc));
+        }
+        return Ok((n / indent.width(), body));
+    }
+
+    //Returns the line without the indentation of the text block if it belongs to it
+    fn text_line(ln : &str, depth : usize, indent : Indent) -> Option<String> {
+        let required = (depth+1) * indent.width();
+        if ln.chars().take_while(|c| *c == indent.unit()).count() >= required {
+            return Some(ln.chars().skip(required).collect());
+        }
+        return None;
+    }
+
+    fn indented(level : usize, loc : &Location, res : Vec<Token>) -> Vec<Token> {
+        let mut line = Vec::<Token>::new();
+        for c in 0..level {
+            line.push(Token{kind:TokenKind::Indent, loc:Location{column:c+1, ..loc.clone()}});
+        }
+        line.extend(res);
+        return line;
+    }
+
+    //A bare '\' at the end of the line joins the next line to this one
+    fn continuation(res : &mut Vec<Token>) -> Option<Location> {
+        let loc = match res.last() {
+            Some(Token{kind:TokenKind::Word(w), loc}) if w == "\\" => loc.clone(),
+            _ => return None
+        };
+        res.pop();
+        return Some(loc);
+    }
 
-    pub fn get_tokens(f : Box<dyn Read>) -> Result<Vec<Vec<String>>, Error> {
-        let mut result = Vec::<Vec<String>>::new();
+    pub fn get_tokens(f : Box<dyn Read>, file : &str, indent : Indent, diags : &mut Diagnostics) -> Result<Vec<Vec<Token>>, Error> {
+        let mut result = Vec::<Vec<Token>>::new();
         let reader = BufReader::new(f);
-        for (_, v) in reader.lines().enumerate() {
+        let mut text : Option<TextBlock> = None;
+        let mut comment : Option<Location> = None;
+        let mut continued : Option<Location> = None;
+        let mut first = None;
+        for (i, v) in reader.lines().enumerate() {
             let data = v?;
-            let res = line_tokens(data)?;
-            if res.len() > 0 {
-                result.push(res);
+            let loc = Location{file:file.to_owned(), line:i+1, column:1};
+            if let Some(t) = text.as_mut() {
+                if data.trim().is_empty() {
+                    t.lines.push(String::new());
+                    continue;
+                } else if let Some(l) = text_line(&data, t.depth, indent) {
+                    t.lines.push(l);
+                    continue;
+                }
+                result.last_mut().unwrap().push(text.take().unwrap().token());
+            }
+            if comment.is_some() {
+                //INSIDE A BLOCK COMMENT: JUST LOOK FOR ITS END
+                if let Some(p) = data.find("*;") {
+                    comment = None;
+                    if !data[p+2..].trim().is_empty() {
+                        diags.push(located(Error::new(ErrorKind::InvalidData, "Unexpected code after the end of a block comment"),
+                            &Location{column:data[..p+2].chars().count()+1, ..loc}));
+                    }
+                }
+                continue;
             }
+            if continued.is_some() {
+                //CONTINUATION LINE: ITS INDENTATION DOES NOT MATTER
+                let body = data.trim_start().to_owned();
+                let base = Location{column:data.chars().count()-body.chars().count()+1, ..loc.clone()};
+                match line_tokens(body, &base, &mut comment) {
+                    Ok(mut res) => if res.len() > 0 {
+                        continued = continuation(&mut res);
+                        result.last_mut().unwrap().extend(res);
+                    }
+                    Err(e) => {
+                        diags.push(e);
+                        continued = None;
+                    }
+                }
+                continue;
+            }
+            let (level, body) = match split_indent(&data, indent, &loc, &mut first) {
+                Ok(x) => x,
+                Err(e) => {
+                    diags.push(e);
+                    continue;
+                }
+            };
+            let base = Location{column:data.chars().count()-body.chars().count()+1, ..loc.clone()};
+            if body.starts_with(";;") {
+                //DOC COMMENT: DESCRIBES THE NEXT BLOCK
+                let doc = &body[2..];
+                let doc = if doc.starts_with(' ') {&doc[1..]} else {doc};
+                result.push(vec![Token{kind:TokenKind::Doc(doc.trim_end().to_owned()), loc:base}]);
+                continue;
+            }
+            if let Some(head) = text_opening(&body, &base, &comment) {
+                let res = match line_tokens(head.clone(), &base, &mut comment) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        diags.push(e);
+                        Vec::new()
+                    }
+                };
+                let column = base.column + head.chars().count();
+                result.push(indented(level, &loc, res));
+                text = Some(TextBlock{depth:level, loc:Location{column:column, ..loc}, lines:Vec::new()});
+                continue;
+            }
+            match line_tokens(body, &base, &mut comment) {
+                Ok(mut res) => if res.len() > 0 {
+                    continued = continuation(&mut res);
+                    result.push(indented(level, &loc, res));
+                }
+                Err(e) => {
+                    //KEEP AN EMPTY LINE SO THE SUB BLOCKS STAY IN THEIR PLACE
+                    diags.push(e);
+                    result.push(indented(level, &loc, Vec::new()));
+                }
+            }
+        }
+        if let Some(t) = text {
+            result.last_mut().unwrap().push(t.token());
+        }
+        if let Some(c) = comment {
+            diags.push(located(Error::new(ErrorKind::InvalidData, "Unterminated block comment"), &c));
+        }
+        if let Some(c) = continued {
+            diags.push(located(Error::new(ErrorKind::InvalidData, "Line continuation at the end of the file"), &c));
         }
         return Ok(result);
     }
 
-    pub fn line_tokens(ln : String) -> Result<Vec<String>, Error> {
-        let mut ret = Vec::<String>::new();
+    //Underscores are only allowed between two digits
+    fn separators_ok(digits : &str, radix : u32) -> bool {
+        let chars : Vec<char> = digits.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            if *c == '_' {
+                let before = i > 0 && chars[i-1].is_digit(radix);
+                let after = i+1 < chars.len() && chars[i+1].is_digit(radix);
+                if !before || !after {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    fn parse_number(text : &str) -> Result<Number, &'static str> {
+        let (neg, body) = match text.chars().next() {
+            Some('-') => (true, &text[1..]),
+            Some('+') => (false, &text[1..]),
+            _ => (false, text)
+        };
+        let (radix, digits) = if body.starts_with("0x") || body.starts_with("0X") {
+            (16, &body[2..])
+        } else if body.starts_with("0b") || body.starts_with("0B") {
+            (2, &body[2..])
+        } else {
+            (10, body)
+        };
+        if digits.is_empty() || !separators_ok(digits, radix) {
+            return Err("Malformed number literal");
+        }
+        let clean : String = digits.chars().filter(|c| *c != '_').collect();
+        if clean.chars().all(|c| c.is_digit(radix)) {
+            let n = match i64::from_str_radix(&clean, radix) {
+                Ok(n) => if neg {-n} else {n},
+                Err(_) => return Err("Integer literal out of range")
+            };
+            return match i32::try_from(n) {
+                Ok(x) => Ok(Number::Int(x)),
+                Err(_) => Err("Integer literal out of range")
+            };
+        } else if radix != 10 {
+            return Err("Malformed number literal");
+        }
+        return match clean.parse::<f64>() {
+            Ok(f) => Ok(Number::Float(if neg {-f} else {f})),
+            Err(_) => Err("Malformed number literal")
+        };
+    }
+
+    fn word(text : String, loc : Location) -> Result<Token, Error> {
+        let mut chars = text.chars();
+        let numeric = match chars.next() {
+            Some(c) if c.is_ascii_digit() => true,
+            Some('-') | Some('+') => match chars.next() {
+                Some('.') => chars.next().map_or(false, |d| d.is_ascii_digit()),
+                Some(d) => d.is_ascii_digit(),
+                None => false
+            },
+            Some('.') => chars.next().map_or(false, |d| d.is_ascii_digit()),
+            _ => false
+        };
+        if !numeric {
+            return Ok(Token{kind:TokenKind::Word(text), loc:loc});
+        }
+        //ONLY THE HEX, BINARY AND SEPARATED FORMS MUST BE NUMBERS, WORDS LIKE 127.0.0.1 ARE KEPT
+        let body = text.trim_start_matches(|c| c == '-' || c == '+').to_lowercase();
+        let strict = body.starts_with("0x") || body.starts_with("0b") || body.contains('_');
+        return match parse_number(&text) {
+            Ok(n) => Ok(Token{kind:TokenKind::Number(n, text), loc:loc}),
+            Err(e) if strict => Err(located(Error::new(ErrorKind::InvalidData, format!("{} '{}'", e, text)), &loc)),
+            Err(_) => Ok(Token{kind:TokenKind::Word(text), loc:loc})
+        };
+    }
+
+    pub fn line_tokens(ln : String, base : &Location, comment : &mut Option<Location>) -> Result<Vec<Token>, Error> {
+        let mut ret = Vec::<Token>::new();
         let mut mxt = false;
         let mut act = String::new();
+        let mut start = 0;
         let mut lit = false;
-        for (l, v) in ln.chars().enumerate() {
-            if lit {
+        let mut quote = false;
+        let mut escaped = false;
+        let mut skip = false;
+        let at = |column : usize| Location{file:base.file.clone(), line:base.line, column:base.column+column};
+        let chars : Vec<char> = ln.chars().collect();
+        for (l, v) in chars.iter().cloned().enumerate() {
+            let next = chars.get(l+1).cloned();
+            if skip {
+                skip = false;
+            } else if comment.is_some() {
+                //BLOCK COMMENT: ENDS WITH '*;'
+                if v == '*' && next == Some(';') {
+                    *comment = None;
+                    skip = true;
+                }
+            } else if lit {
                 act.push(v);
+            } else if quote {
+                //QUOTED STRING: ENDS AT THE FIRST UNESCAPED '"'
+                if v == '"' && !escaped {
+                    ret.push(Token{kind:TokenKind::Str(unescape(&act, &at(start))?), loc:at(start)});
+                    act = String::new();
+                    quote = false;
+                } else {
+                    escaped = v == '\\' && !escaped;
+                    act.push(v);
+                }
             } else {
                 match v {
                     '\t' => {
                         //TAB: FOR SUB STATEMENTS
                         if !mxt && act.chars().count() == 0 {
-                            ret.push(v.to_string());
+                            ret.push(Token{kind:TokenKind::Indent, loc:at(l)});
                         } else {
-                            return Err(Error::new(ErrorKind::InvalidData, "The 'Tabs' must be at the beginning of the line"))
+                            return Err(located(Error::new(ErrorKind::InvalidData, "The 'Tabs' must be at the beginning of the line"), &at(l)))
                         }
                     }
                     '#' => {
                         //LITERAL (GOOD FOR STRINGS)
                         mxt = true;
                         if act.chars().count() > 0 {
-                            ret.push(act.clone());
+                            ret.push(word(act, at(start))?);
+                            act = String::new();
                         }
+                        start = l;
                         lit = true;
                     }
+                    '"' => {
+                        //QUOTED STRING (ALLOWS ESCAPES AND MORE ARGUMENTS AFTER IT)
+                        mxt = true;
+                        if act.chars().count() > 0 {
+                            ret.push(word(act, at(start))?);
+                            act = String::new();
+                        }
+                        start = l;
+                        quote = true;
+                    }
+                    ';' if next == Some('*') && chars.get(l+2) != Some(&'*') => {
+                        //BLOCK COMMENT (CAN SPAN SEVERAL LINES), ;** IS STILL A LINE COMMENT FOR BANNERS
+                        if act.chars().count() > 0 {
+                            ret.push(word(act, at(start))?);
+                            act = String::new();
+                        }
+                        *comment = Some(at(l));
+                        skip = true;
+                    }
                     ';' => {
                         //AVOID NEXT (GOOD FOR COMMENTS)
                         if act.chars().count() > 0 {
-                            ret.push(act.clone());
+                            ret.push(word(act, at(start))?);
+                            act = String::new();
                         }
                         break;
                     }
-                    '*' | '$' | '!' | ':' => {
+                    //'&' ONLY STARTS A PROC REFERENCE WHEN A NAME FOLLOWS IT, OTHERWISE IT IS PART OF A WORD
+                    '*' | '$' | '!' | ':' | '&' if v != '&' || (act.is_empty() && next.map_or(false, |n| n.is_alphanumeric() || n == '_')) => {
                         mxt = true;
                         if act.chars().count() > 0 {
-                            ret.push(act);
+                            ret.push(word(act, at(start))?);
                             act = String::new();
                         }
-                        ret.push(v.to_string());
+                        ret.push(Token{kind:TokenKind::Sigil(v), loc:at(l)});
                     }
                     ' ' => {
                         if act.chars().count() > 0 {
-                            ret.push(act);
+                            ret.push(word(act, at(start))?);
                             act = String::new();
                         }
                     }
+                    '=' if act.starts_with(|c : char| c.is_alphabetic() || c == '_') => {
+                        //KEYWORD ARGUMENT: THE NEXT TOKEN IS ITS VALUE
+                        ret.push(Token{kind:TokenKind::Key(act), loc:at(start)});
+                        act = String::new();
+                    }
                     _ => {
                         mxt = true;
+                        if act.chars().count() == 0 {
+                            start = l;
+                        }
                         act.push(v);
                     }
                 };
             }
-            if l == ln.chars().count()-1 && act.chars().count() > 0{
-                ret.push(act.clone());
+        }
+        if quote {
+            return Err(located(Error::new(ErrorKind::InvalidData, "Unterminated string literal"), &at(start)));
+        } else if lit {
+            if act.chars().count() > 0 {
+                ret.push(Token{kind:TokenKind::Str(act), loc:at(start)});
             }
+        } else if act.chars().count() > 0 {
+            ret.push(word(act, at(start))?);
         }
         if !mxt {
             ret.clear();
         }
         return Ok(ret);
     }
+
+    fn unescape(raw : &str, loc : &Location) -> Result<String, Error> {
+        let mut res = String::new();
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                res.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => res.push('\n'),
+                Some('t') => res.push('\t'),
+                Some('r') => res.push('\r'),
+                Some('0') => res.push('\0'),
+                Some('\\') => res.push('\\'),
+                Some('"') => res.push('"'),
+                Some('u') => {
+                    let mut hex = String::new();
+                    let mut closed = false;
+                    if chars.next() == Some('{') {
+                        while let Some(h) = chars.next() {
+                            if h == '}' {
+                                closed = true;
+                                break;
+                            }
+                            hex.push(h);
+                        }
+                    }
+                    let code = match u32::from_str_radix(&hex, 16) {
+                        Ok(n) if closed => std::char::from_u32(n),
+                        _ => None
+                    };
+                    match code {
+                        Some(n) => res.push(n),
+                        None => return Err(located(Error::new(ErrorKind::InvalidData, "Invalid unicode escape, expected \\u{XXXX}"), loc))
+                    }
+                }
+                Some(x) => return Err(located(Error::new(ErrorKind::InvalidData, format!("Unknown escape sequence '\\{}'", x)), loc)),
+                None => return Err(located(Error::new(ErrorKind::InvalidData, "Unterminated escape sequence"), loc))
+            }
+        }
+        return Ok(res);
+    }
 }
 
 pub mod structure {
 
-    use crate::core::{Block};
+    use crate::core::{Block, Token, TokenKind, Term, Call, CallMode, Statement, Location};
+    use crate::core::error::{located, Diagnostics};
     use std::io::{Error, ErrorKind};
 
-    pub fn generate_blocks(lines : Vec<Vec<String>>) -> Result<Vec<Block>, Error> {
-        let mut res = Block{subs:Vec::new(),data:Vec::new()};
+    fn level(line : &Vec<Token>) -> usize {
+        line.iter().take_while(|t| t.kind == TokenKind::Indent).count()
+    }
+
+    fn is_doc(line : &Vec<Token>) -> bool {
+        match line.first() {
+            Some(Token{kind:TokenKind::Doc(_), ..}) => true,
+            _ => false
+        }
+    }
+
+    //Every line can be at most one level deeper than the previous one, the deeper ones are
+    //reported and placed one level under the previous line so the rest of the tree is kept
+    pub fn validate_indentation(lines : &Vec<Vec<Token>>, diags : &mut Diagnostics) -> Vec<usize> {
+        let mut levels = Vec::<usize>::new();
+        let mut prev : Option<usize> = None;
         for v in lines.iter() {
+            if is_doc(v) {
+                levels.push(0);
+                continue;
+            }
+            let lev = level(v);
+            let max = match prev {
+                Some(p) => p + 1,
+                None => 0
+            };
+            if lev > max {
+                let loc = &v[lev.min(v.len()-1)].loc;
+                let msg = format!("Indentation jumps from level {} to {} at line {}", prev.unwrap_or(0), lev, loc.line);
+                diags.push(located(Error::new(ErrorKind::InvalidData, msg), loc));
+            }
+            levels.push(lev.min(max));
+            prev = Some(lev.min(max));
+        }
+        return levels;
+    }
+
+    fn name_after(tokens : &[Token], idx : usize) -> Result<String, Error> {
+        let sigil = &tokens[idx-1];
+        return match tokens.get(idx) {
+            Some(Token{kind:TokenKind::Word(w), ..}) => Ok(w.clone()),
+            Some(t) => Err(located(Error::new(ErrorKind::InvalidData, format!("Expected a name after '{}'", sigil.text())), &t.loc)),
+            None => Err(located(Error::new(ErrorKind::InvalidData, format!("Expected a name after '{}'", sigil.text())), &sigil.loc))
+        };
+    }
+
+    fn parse_terms(tokens : &[Token]) -> Result<Vec<Term>, Error> {
+        let mut res = Vec::<Term>::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i].kind {
+                TokenKind::Word(w) => res.push(Term::Word(w.clone())),
+                TokenKind::Str(s) => res.push(Term::Str(s.clone())),
+                TokenKind::Number(n, t) => res.push(Term::Number(*n, t.clone())),
+                TokenKind::Sigil('$') | TokenKind::Sigil('!') => {
+                    i += 1;
+                    res.push(Term::Ref(name_after(tokens, i)?));
+                }
+                TokenKind::Sigil('&') => {
+                    i += 1;
+                    res.push(Term::Proc(name_after(tokens, i)?));
+                }
+                TokenKind::Sigil(c) => res.push(Term::Word(c.to_string())),
+                TokenKind::Key(k) => {
+                    //WITHOUT A VALUE AFTER IT 'name=' IS JUST A WORD
+                    let value = match parse_terms(&tokens[i+1..])?.into_iter().next() {
+                        Some(Term::Key(..)) | None => {
+                            res.push(Term::Word(format!("{}=", k)));
+                            i += 1;
+                            continue;
+                        }
+                        Some(v) => v
+                    };
+                    i += if let TokenKind::Sigil('$') | TokenKind::Sigil('!') | TokenKind::Sigil('&') = tokens[i+1].kind {2} else {1};
+                    res.push(Term::Key(k.clone(), Box::new(value)));
+                }
+                TokenKind::Indent | TokenKind::Doc(_) => {}
+            }
+            i += 1;
+        }
+        return Ok(res);
+    }
+
+    pub fn parse_statement(tokens : &[Token], loc : &Location) -> Result<Statement, Error> {
+        return match &tokens[0].kind {
+            TokenKind::Sigil(':') => {
+                if tokens.len() < 2 {
+                    return Err(located(Error::new(ErrorKind::InvalidData, "Block tag must have a name"), loc));
+                }
+                Ok(Statement::Tag(name_after(tokens, 1)?, parse_terms(&tokens[2..])?))
+            }
+            TokenKind::Sigil('*') | TokenKind::Sigil('$') => {
+                let mode = if tokens[0].kind == TokenKind::Sigil('*') {CallMode::Forced} else {CallMode::Variable};
+                Ok(Statement::Call(Call{mode:mode, name:name_after(tokens, 1)?, args:parse_terms(&tokens[2..])?}))
+            }
+            TokenKind::Word(w) => Ok(Statement::Call(Call{mode:CallMode::Plain, name:w.clone(), args:parse_terms(&tokens[1..])?})),
+            _ => Ok(Statement::Row(parse_terms(tokens)?))
+        };
+    }
+
+    pub fn generate_blocks(lines : Vec<Vec<Token>>, diags : &mut Diagnostics) -> Vec<Block> {
+        let levels = validate_indentation(&lines, diags);
+        let mut res = Block{subs:Vec::new(), stmt:Statement::Row(Vec::new()), loc:Default::default(), doc:Vec::new()};
+        let mut doc = Vec::<String>::new();
+        for (v, inc) in lines.iter().zip(levels.into_iter()) {
+            if let Some(Token{kind:TokenKind::Doc(d), ..}) = v.first() {
+                doc.push(d.clone());
+                continue;
+            }
             let mut act = &mut res;
-            let mut inc = 0;
-            let mut trimed = Vec::<String>::new();
-            for i in v.iter() {
-                if i == "\t" {
-                    inc += 1;
-                } else {
-                    trimed.push(String::from(i));
+            let trimed = &v[level(v)..];
+            let loc = match trimed.first().or(v.first()) {
+                Some(t) => t.loc.clone(),
+                None => Location::default()
+            };
+            for _ in 0..inc {
+                act = act.subs.last_mut().unwrap();
+            }
+            //LINES THAT COULD NOT BE READ ARE KEPT AS INVALID STATEMENTS
+            let stmt = if trimed.is_empty() {
+                Statement::Invalid
+            } else {
+                match parse_statement(trimed, &loc) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        diags.push(e);
+                        Statement::Invalid
+                    }
                 }
+            };
+            act.subs.push(Block{subs:Vec::new(), stmt:stmt, loc:loc, doc:doc.drain(..).collect()})
+        }
+        return res.subs;
+    }
+
+    //Tag blocks only make sense as arguments of a call, never as statements
+    pub fn check_tags(blocks : &Vec<Block>, statements : bool, diags : &mut Diagnostics) {
+        for b in blocks.iter() {
+            match &b.stmt {
+                Statement::Tag(t, _) => {
+                    if statements {
+                        diags.push(located(Error::new(ErrorKind::InvalidData,
+                            format!("Block tag ':{}' must be a sub block of a call", t)), &b.loc));
+                    }
+                    check_tags(&b.subs, true, diags);
+                }
+                _ => check_tags(&b.subs, false, diags)
             }
-            let mut k = inc;
-            while k > 0 {
-                let idx = act.subs.len()-1;
-                match act.subs.get_mut(idx) {
-                    Some(n) => act = n,
-                    None => return Err(Error::new(ErrorKind::InvalidData, "Too deep level"))
-                };
-                k -= 1;
+        }
+    }
+}
+
+pub mod error {
+
+    use std::fmt;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, Error, ErrorKind};
+    use crate::core::Location;
+
+    //Error with the place where it happened, proc is empty for the errors of the front end
+    #[derive(Debug)]
+    pub struct SourceError {
+        pub loc : Location,
+        pub proc : String,
+        pub cause : Error
+    }
+
+    impl fmt::Display for SourceError {
+        fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}: {}", self.loc, self.cause)
+        }
+    }
+
+    impl std::error::Error for SourceError {}
+
+    //Error raised by THROW, the kind lets the handlers tell errors apart
+    #[derive(Debug)]
+    pub struct Thrown {
+        pub kind : String,
+        pub message : String
+    }
+
+    impl fmt::Display for Thrown {
+        fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}: {}", self.kind, self.message)
+        }
+    }
+
+    impl std::error::Error for Thrown {}
+
+    //Every error found by the front end, so all of them can be reported at once,
+    //the warnings are reported too but they don't stop anything
+    #[derive(Debug)]
+    pub struct Diagnostics {
+        pub errors : Vec<Error>,
+        pub warnings : Vec<Error>,
+        pub summary : &'static str
+    }
+
+    impl fmt::Display for Diagnostics {
+        fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} error(s) found, {}", self.errors.len(), self.summary)
+        }
+    }
+
+    impl std::error::Error for Diagnostics {}
+
+    fn sort_by_location(list : &mut Vec<Error>) {
+        list.sort_by_key(|e| match location(e) {
+            Some(l) => (false, l.file.clone(), l.line, l.column),
+            None => (true, String::new(), 0, 0)
+        });
+    }
+
+    impl Diagnostics {
+        pub fn new(summary : &'static str) -> Self {
+            return Diagnostics{errors:Vec::new(), warnings:Vec::new(), summary:summary};
+        }
+
+        pub fn push(&mut self, e : Error) {
+            self.errors.push(e);
+        }
+
+        pub fn warn(&mut self, e : Error) {
+            self.warnings.push(e);
+        }
+
+        //The warnings are given back when there are no errors
+        pub fn check(mut self) -> Result<Vec<Error>, Error> {
+            sort_by_location(&mut self.warnings);
+            if self.errors.is_empty() {
+                return Ok(self.warnings);
+            }
+            sort_by_location(&mut self.errors);
+            return Err(Error::new(ErrorKind::InvalidData, self));
+        }
+    }
+
+    //Process exit status when the program can't be loaded or fails while running
+    pub const FRONT_END_FAILURE : i32 = 65;
+    pub const RUNTIME_FAILURE : i32 = 70;
+
+    //Raised by EXIT, it unwinds every running proc up to ProgramInstance::run
+    #[derive(Debug)]
+    pub struct Exit(pub i32);
+
+    impl fmt::Display for Exit {
+        fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Exit with status {}", self.0)
+        }
+    }
+
+    impl std::error::Error for Exit {}
+
+    pub fn exit(code : i32) -> Error {
+        return Error::new(ErrorKind::Interrupted, Exit(code));
+    }
+
+    pub fn exit_status(e : &Error) -> Option<i32> {
+        return e.get_ref().and_then(|r| r.downcast_ref::<Exit>()).map(|x| x.0);
+    }
+
+    //Attaches the location to the error unless a deeper block already did it
+    pub fn located(e : Error, loc : &Location) -> Error {
+        if loc.line == 0 || location(&e).is_some() || exit_status(&e).is_some() {
+            return e;
+        }
+        return Error::new(e.kind(), SourceError{loc:loc.clone(), proc:String::new(), cause:e});
+    }
+
+    //Names the proc where the error happened unless a deeper proc already did it
+    pub fn in_proc(mut e : Error, name : &str) -> Error {
+        if let Some(s) = e.get_mut().and_then(|r| r.downcast_mut::<SourceError>()) {
+            if s.proc.is_empty() {
+                s.proc = name.to_owned();
+            }
+        }
+        return e;
+    }
+
+    pub fn source_error(e : &Error) -> Option<&SourceError> {
+        return e.get_ref().and_then(|r| r.downcast_ref::<SourceError>());
+    }
+
+    //Kind and message of the error without its location
+    pub fn kind_and_message(e : &Error) -> (String, String) {
+        let cause = match source_error(e) {
+            Some(s) => &s.cause,
+            None => e
+        };
+        return match cause.get_ref().and_then(|r| r.downcast_ref::<Thrown>()) {
+            Some(t) => (t.kind.clone(), t.message.clone()),
+            None => (kind_name(cause.kind()).to_owned(), cause.to_string())
+        };
+    }
+
+    //Kinds of the errors raised by the interpreter as TRY shows them
+    fn kind_name(kind : ErrorKind) -> &'static str {
+        return match kind {
+            ErrorKind::InvalidInput => "INVALID-ARGUMENT",
+            ErrorKind::InvalidData => "INVALID-DATA",
+            ErrorKind::NotFound => "NOT-FOUND",
+            ErrorKind::PermissionDenied => "NOT-ALLOWED",
+            ErrorKind::AlreadyExists => "ALREADY-EXISTS",
+            ErrorKind::UnexpectedEof => "END-OF-INPUT",
+            _ => "ERROR"
+        };
+    }
+
+    pub fn location(e : &Error) -> Option<&Location> {
+        return source_error(e).map(|s| &s.loc);
+    }
+
+    fn source_line(loc : &Location) -> Option<String> {
+        let f = File::open(&loc.file).ok()?;
+        return match BufReader::new(f).lines().nth(loc.line-1) {
+            Some(Ok(l)) => Some(l),
+            _ => None
+        };
+    }
+
+    pub fn report(e : &Error) {
+        if let Some(d) = e.get_ref().and_then(|r| r.downcast_ref::<Diagnostics>()) {
+            for x in d.warnings.iter() {
+                warning(x);
+            }
+            for x in d.errors.iter() {
+                report(x);
+            }
+        }
+        print("Error", e);
+    }
+
+    pub fn warning(e : &Error) {
+        print("Warning", e);
+    }
+
+    fn print(label : &str, e : &Error) {
+        eprintln!("{}: {}", label, e);
+        if let Some(loc) = location(e) {
+            if let Some(ln) = source_line(loc) {
+                let mut caret = String::new();
+                for c in ln.chars().take(loc.column-1) {
+                    caret.push(if c == '\t' {'\t'} else {' '});
+                }
+                caret.push('^');
+                eprintln!("{}", ln);
+                eprintln!("{}", caret);
             }
-            act.subs.push(Block{subs:Vec::new(), data:trimed})
         }
-        return Ok(res.subs);
     }
 }
 
 pub mod runtime {
     use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::cell::RefCell;
     use std::io::{Error, ErrorKind};
-    use crate::core::{ProgramInstance, Proc, Block};
-    use crate::types::{join_values, ETVoid, ETInt, ETFloat, ETList, ETMap, ETLiteral, ETString, ETBlock, ETType};
+    use crate::core::{ProgramInstance, Proc, Param, Block, Statement, Call, CallMode, Term};
+    use crate::core::error::{located, in_proc, Thrown};
+    use crate::types::{join_values, ETVoid, ETInt, ETFloat, ETList, ETMap, ETLiteral, ETString, ETBlock, ETType, ETRef, ETKeyword, ETRange, ETProc, ETProcRef, ETNumber};
 
     #[derive(Clone)]
     pub struct RunningInstance {
         pub name : String,
         pub entry_point : String,
-        pub methods : Vec<Box<dyn ProcExecution>>
+        pub methods : Rc<Vec<Box<dyn ProcExecution>>>,
+        pub globals : Rc<RefCell<HashMap<String, Box<dyn Value>>>>,
+        pub constants : Rc<HashMap<String, Box<dyn Value>>>,
+        pub calls : Rc<RefCell<Vec<String>>>,
+        pub max_depth : usize,
+        pub stack : (usize, usize)
     }
 
-    fn parse_params(c : &mut Context, params : Vec<Box<dyn Value>>) -> Result<Vec<Box<dyn Value>>, Error> {
-        let mut res = Vec::new();
-        let mut nxc = false;
+    //Calls that can be nested before RECURSION-LIMIT is raised
+    pub const DEFAULT_MAX_DEPTH : usize = 1000;
+
+    //Native stack kept free for the standard procs running between two checks
+    const STACK_MARGIN : usize = 256 * 1024;
+
+    //Address of a local, it tells how deep the native stack is
+    #[inline(never)]
+    fn stack_address() -> usize {
+        let here = 0u8;
+        return &here as *const u8 as usize;
+    }
+
+    //Names of the running procs, the repeated ones are written once with the number of calls
+    fn call_chain(calls : &Vec<String>) -> String {
+        let mut res = Vec::<(String, usize)>::new();
+        for x in calls.iter() {
+            match res.last_mut() {
+                Some(l) if &l.0 == x => l.1 += 1,
+                _ => res.push((x.clone(), 1))
+            }
+        }
+        return res.into_iter().map(|(n, i)| if i > 1 {format!("{} (x{})", n, i)} else {n}).collect::<Vec<String>>().join(" -> ");
+    }
+
+    pub fn arity_error(name : &str, min : usize, max : Option<usize>, got : usize) -> Error {
+        let expected = match max {
+            None => format!("at least {}", min),
+            Some(m) if m == min => m.to_string(),
+            Some(m) => format!("{} to {}", min, m)
+        };
+        return Error::new(ErrorKind::InvalidInput, format!("{} expects {} argument(s) and got {}", name, expected, got));
+    }
+
+    pub fn resolve_param(c : &mut Context, p : Box<dyn Value>) -> Result<Box<dyn Value>, Error> {
+        if let Some(name) = p.proc_ref() {
+            return Ok(Box::new(ETProc(c.get_proc(false, &name)?)));
+        }
+        return match p.reference() {
+            Some(name) => if let Ok(v) = c.get_var(&name) {
+                Ok(v)
+            } else {
+                c.get_proc(false, &name)?.run(Vec::new(), c)
+            }
+            None => Ok(p)
+        }
+    }
+
+    //Keyword arguments are moved to the position of the parameter they name, procs
+    //without declared parameters get them as the 'name=value' literal
+    fn parse_params(c : &mut Context, pr : &Box<dyn ProcExecution>, params : Vec<Box<dyn Value>>) -> Result<Vec<Box<dyn Value>>, Error> {
+        let mut res = Vec::<Box<dyn Value>>::new();
+        let mut keys = Vec::<(String, Box<dyn Value>)>::new();
+        let declared : Vec<Param> = pr.params();
         for p in params.into_iter() {
-            if p.is_literal() && (p.literal() == "$" || p.literal() == "!") {
-                nxc = true;
-            } else if nxc {
-                if let Ok(v) = c.get_var(&p.literal()) {
-                    res.push(v);
-                } else {
-                    res.push(c.get_proc(false, &p.literal())?.run(Vec::new(), c)?)
+            match p.keyword() {
+                Some((k, v)) if declared.is_empty() => {
+                    let v = resolve_param(c, v)?;
+                    res.push(Box::new(ETLiteral(format!("{}={}", k, v.literal()))));
                 }
-                nxc = false;
-            } else {
-                res.push(p);
+                Some((k, v)) => keys.push((k, resolve_param(c, v)?)),
+                None if pr.lazy() => res.push(p),
+                None => res.push(resolve_param(c, p)?)
             }
         }
-        return Ok(res);
+        if keys.is_empty() {
+            return Ok(res);
+        }
+        let fixed : Vec<Param> = declared.iter().filter(|p| !p.variadic).cloned().collect();
+        if res.len() > fixed.len() {
+            if fixed.len() < declared.len() {
+                return Err(Error::new(ErrorKind::InvalidInput, "Keyword arguments can't be combined with variadic arguments"));
+            }
+            let (min, max) = pr.arity().unwrap_or((0, None));
+            return Err(arity_error(&pr.name(), min, max, res.len() + keys.len()));
+        }
+        let declared = fixed;
+        let mut slots : Vec<Option<Box<dyn Value>>> = res.into_iter().map(Some).collect();
+        for (k, v) in keys.into_iter() {
+            let idx = match declared.iter().position(|p| p.name == k) {
+                Some(i) => i,
+                None => return Err(Error::new(ErrorKind::InvalidInput, format!("{} has no parameter named '{}'", pr.name(), k)))
+            };
+            if slots.len() <= idx {
+                slots.resize_with(idx+1, || None);
+            } else if slots[idx].is_some() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Argument '{}' given twice", k)));
+            }
+            slots[idx] = Some(v);
+        }
+        let mut ordered = Vec::new();
+        for (i, s) in slots.into_iter().enumerate() {
+            match s.or(default_value(&declared[i])) {
+                Some(v) => ordered.push(v),
+                None => return Err(Error::new(ErrorKind::InvalidInput, format!("Missing argument '{}' of {}", declared[i].name, pr.name())))
+            }
+        }
+        return Ok(ordered);
+    }
+
+    fn default_value(p : &Param) -> Option<Box<dyn Value>> {
+        return p.default.as_ref().and_then(|t| term_values(&vec![t.clone()]).pop());
     }
 
-    impl<'a> Block {
-        pub fn head_is(&self, name : &'a str) -> bool {
-            self.data[0] == name
+    fn term_values(terms : &Vec<Term>) -> Vec<Box<dyn Value>> {
+        let mut res = Vec::<Box<dyn Value>>::new();
+        for t in terms.iter() {
+            res.push(match t {
+                Term::Word(w) => Box::new(ETLiteral(w.clone())),
+                Term::Number(n, t) => Box::new(ETNumber(*n, t.clone())),
+                Term::Str(s) => Box::new(ETString(s.clone())),
+                Term::Ref(r) => Box::new(ETRef(r.clone())),
+                Term::Proc(p) => Box::new(ETProcRef(p.clone())),
+                Term::Key(k, v) => match term_values(&vec![(**v).clone()]).pop() {
+                    Some(x) => Box::new(ETKeyword(k.clone(), x)),
+                    None => Box::new(ETVoid{})
+                }
+            });
+        }
+        return res;
+    }
+
+    impl Block {
+        pub fn tag_args(&self) -> Vec<Box<dyn Value>> {
+            match &self.stmt {
+                Statement::Tag(_, terms) => term_values(terms),
+                _ => Vec::new()
+            }
+        }
+
+        pub fn tag(&self) -> Option<&str> {
+            match &self.stmt {
+                Statement::Tag(t, _) => Some(t),
+                _ => None
+            }
         }
 
-        pub fn cut_head(&self) -> (Self, usize, bool) {
-            let mut data = self.data.clone();
-            if data.len() < 1 {
-                return (self.clone(), data.len(), false);
+        //Values of the block when it works as a row of arguments
+        fn row(&self) -> Vec<Box<dyn Value>> {
+            match &self.stmt {
+                Statement::Call(call) => {
+                    let mut res : Vec<Box<dyn Value>> = vec![Box::new(ETLiteral(call.name.clone()))];
+                    res.extend(term_values(&call.args));
+                    res
+                }
+                Statement::Row(terms) => term_values(terms),
+                Statement::Tag(..) | Statement::Invalid => Vec::new()
             }
-            return (Block{subs:self.subs.clone(), data:data.drain(1..).collect()}, data.len()-1, true);
         }
 
         pub fn run_named(&self, con : &mut Context) -> Result<Box<dyn Value>, Error> {
@@ -183,61 +1181,86 @@ pub mod runtime {
                 lastval = match x.run(con, true)?.last() {
                     Some(n) => if n.len() > 1 {Box::new(ETList(n.clone()))} else {n[0].clone()},
                     None => Box::new(ETVoid{})
+                };
+                //RETURN OR STOP: THE REST OF THE BLOCK IS SKIPPED
+                if !con.running {
+                    break;
                 }
             }
             return Ok(lastval);
         }
 
         pub fn run(&self, c : &mut Context, proc_scope : bool) -> Result<Vec<Vec<Box<dyn Value>>>, Error> {
-            let x = if self.data[0] == "*" || self.data[0] == "$" || self.data[0] == ":" {1} else {0};
-            if self.data.len()-1 < x {
-                return Err(Error::new(ErrorKind::InvalidData, "Expected function"));
-            } else if self.data[0] == "*" && proc_scope {
-                return Err(Error::new(ErrorKind::InvalidData, "Not necessary execution specifier"));
-            } else if x > 0 && self.data[0] == ":" {
-                let mut nblock = self.clone();
-                nblock.data.remove(0);
-                if nblock.data.len() != 1 {
-                    return Err(Error::new(ErrorKind::InvalidData, "Block tag must have just tag's name as member"));
-                }
-                return Ok(vec![vec![Box::new(ETBlock(nblock))]]);
-            } else if proc_scope || x > 0 {
-                let pr : Box<dyn ProcExecution> = c.get_proc(self.data[0] == "$", &self.data[x])?;
-                let mut result : Vec<Vec<Box<dyn Value>>> = Vec::new();
-                let mut args : Vec<Box<dyn Value>> =
-                    ETLiteral::literal_array(&self.data.clone().drain((x+1)..).collect());
-                if self.data[0] == "$" {
-                    if let Some(n) = c.variables.get(&self.data[x]) {
-                        args.insert(0, n.target().clone());
-                    }
-                }
-                if self.subs.len() > 0 {
-                    for x in self.subs.iter() {
-                        for v in x.run(c, false)? {
-                            let res = parse_params(c, join_values(args.clone(), v.clone()))?;
-                            let ret = pr.run(res, c)?;
-                            result.push(vec![ret]);
+            c.instance.check_stack().map_err(|e| located(e, &self.loc))?;
+            return self.execute(c, proc_scope).map_err(|e| located(e, &self.loc));
+        }
+
+        fn execute(&self, c : &mut Context, proc_scope : bool) -> Result<Vec<Vec<Box<dyn Value>>>, Error> {
+            match &self.stmt {
+                Statement::Tag(..) => {
+                    return Ok(vec![vec![Box::new(ETBlock(self.clone()))]]);
+                }
+                Statement::Call(call) if proc_scope || call.mode != CallMode::Plain => {
+                    if call.mode == CallMode::Forced && proc_scope {
+                        return Err(Error::new(ErrorKind::InvalidData, "Not necessary execution specifier"));
+                    }
+                    let pr : Box<dyn ProcExecution> = c.get_proc(call.mode == CallMode::Variable, &call.name)?;
+                    let mut result : Vec<Vec<Box<dyn Value>>> = Vec::new();
+                    let mut args : Vec<Box<dyn Value>> = term_values(&call.args);
+                    //A PROC REFERENCE IS CALLED WITH THE ARGUMENTS ALONE
+                    if call.mode == CallMode::Variable {
+                        if let Some(n) = c.variables.get(&call.name) {
+                            if n.procedure().is_none() {
+                                args.insert(0, n.target().clone());
+                            }
                         }
                     }
-                } else {
-                    let ret = pr.run(parse_params(c, args)?, c)?;
-                    result.push(vec![ret])
+                    if self.subs.len() > 0 && pr.joins_rows() {
+                        for x in self.subs.iter() {
+                            for v in x.run(c, false)? {
+                                args = join_values(args, v);
+                            }
+                        }
+                        let ret = pr.run(parse_params(c, &pr, args)?, c)?;
+                        result.push(vec![ret])
+                    } else if self.subs.len() > 0 {
+                        for x in self.subs.iter() {
+                            for v in x.run(c, false)? {
+                                let res = parse_params(c, &pr, join_values(args.clone(), v.clone()))?;
+                                let ret = pr.run(res, c)?;
+                                result.push(vec![ret]);
+                                if !c.running {
+                                    return Ok(result);
+                                }
+                            }
+                        }
+                    } else {
+                        let ret = pr.run(parse_params(c, &pr, args)?, c)?;
+                        result.push(vec![ret])
+                    }
+                    return Ok(result);
                 }
-                return Ok(result);
-            } else {
-                let mut total : Vec<Vec<Box<dyn Value>>> = Vec::new();
-                let local : Vec<Box<dyn Value>> = ETLiteral::literal_array(&self.data.clone());
-                if self.subs.len() > 0 {
-                    for x in self.subs.iter() {
-                        for v in x.run(c, false)? {
-                            let res = join_values(local.clone(), v.clone());
-                            total.push(res);
+                Statement::Row(_) if proc_scope => {
+                    return Err(Error::new(ErrorKind::InvalidData, "Expected proc name"));
+                }
+                Statement::Invalid => {
+                    return Err(Error::new(ErrorKind::InvalidData, "Invalid statement"));
+                }
+                _ => {
+                    let mut total : Vec<Vec<Box<dyn Value>>> = Vec::new();
+                    let local : Vec<Box<dyn Value>> = self.row();
+                    if self.subs.len() > 0 {
+                        for x in self.subs.iter() {
+                            for v in x.run(c, false)? {
+                                let res = join_values(local.clone(), v.clone());
+                                total.push(res);
+                            }
                         }
+                    } else {
+                        total.push(local);
                     }
-                } else {
-                    total.push(local);
+                    return Ok(total);
                 }
-                return Ok(total);
             }
         }
     }
@@ -247,16 +1270,94 @@ pub mod runtime {
             self.name.clone()
         }
 
+        fn params(&self) -> Vec<Param> {
+            self.params.clone()
+        }
+
+        fn arity(&self) -> Option<(usize, Option<usize>)> {
+            if self.params.is_empty() {
+                return None;
+            }
+            let required = self.params.iter().filter(|p| !p.variadic && p.default.is_none()).count();
+            return Some((required, if self.params.last().map_or(false, |p| p.variadic) {None} else {Some(self.params.len())}));
+        }
+
         fn run(&self, input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
-            let mut context = Context::new(c.instance.clone(), input);
-            for b in self.mems.iter() {
-                if let Err(e) = b.run(&mut context, true) {
-                    return Err(e);
-                } else if !context.running {
-                    break;
+            c.instance.enter(&self.name)?;
+            let res = self.execute(input, c);
+            c.instance.leave();
+            return res;
+        }
+    }
+
+    impl Proc {
+        //A SELF CALL AT THE END STARTS THE PROC AGAIN INSTEAD OF NESTING A NEW CALL
+        fn execute(&self, mut input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
+            let mut result : Option<Box<dyn Value>> = None;
+            'call: loop {
+                let mut context = Context::new(c.instance.clone(), input.clone());
+                context.namespace = self.namespace.clone();
+                context.proc = self.name.clone();
+                self.bind(input, &mut context)?;
+                for (i, b) in self.mems.iter().enumerate() {
+                    if i+1 == self.mems.len() {
+                        if let Some((call, returned)) = self.tail_call(b, &context) {
+                            let pr = context.get_proc(false, &call.name)?;
+                            input = parse_params(&mut context, &pr, term_values(&call.args))
+                                .map_err(|e| in_proc(located(e, &b.loc), &self.name))?;
+                            //WITHOUT RETURN THE VALUE OF THE SELF CALL IS DISCARDED, AS IN A NESTED CALL
+                            if !returned && result.is_none() {
+                                result = Some(context.ret);
+                            }
+                            continue 'call;
+                        }
+                    }
+                    if let Err(e) = b.run(&mut context, true) {
+                        return Err(in_proc(e, &self.name));
+                    } else if !context.running {
+                        break;
+                    }
+                }
+                return Ok(result.unwrap_or(context.ret));
+            }
+        }
+
+        //Call to this proc as a statement or as the only value of RETURN
+        fn tail_call<'b>(&self, b : &'b Block, c : &Context) -> Option<(&'b Call, bool)> {
+            let is = |call : &Call, name : &str| c.get_proc(false, &call.name).map_or(false, |p| p.name() == name);
+            let (call, returned) = match &b.stmt {
+                Statement::Call(r) if r.mode == CallMode::Plain && r.args.is_empty() && b.subs.len() == 1 && is(r, "RETURN") => match &b.subs[0].stmt {
+                    Statement::Call(x) if x.mode == CallMode::Forced && b.subs[0].subs.is_empty() => (x, true),
+                    _ => return None
                 }
+                Statement::Call(x) if x.mode == CallMode::Plain && b.subs.is_empty() => (x, false),
+                _ => return None
+            };
+            return if is(call, &self.name) {Some((call, returned))} else {None};
+        }
+
+        //PROCS WITHOUT DECLARED PARAMETERS ACCEPT ANY ARGUMENTS THROUGH ARGS
+        fn bind(&self, mut input : Vec<Box<dyn Value>>, context : &mut Context) -> Result<(), Error> {
+            let (required, max) = match self.arity() {
+                Some(a) => a,
+                None => return Ok(())
+            };
+            if input.len() < required || max.map_or(false, |m| input.len() > m) {
+                return Err(arity_error(&self.name, required, max, input.len()));
+            }
+            let variadic = max.is_none();
+            let fixed = if variadic {self.params.len()-1} else {self.params.len()};
+            for p in self.params[input.len().min(fixed)..fixed].iter() {
+                input.extend(default_value(p));
+            }
+            let rest = input.split_off(fixed);
+            for (p, v) in self.params.iter().zip(input.into_iter()) {
+                context.variables.insert(p.name.clone(), v);
+            }
+            if variadic {
+                context.variables.insert(self.params[fixed].name.clone(), Box::new(ETList(rest)));
             }
-            return Ok(context.ret);
+            return Ok(());
         }
     }
 
@@ -266,7 +1367,52 @@ pub mod runtime {
             for x in program.methods.into_iter() {
                 allm.push(Box::new(x));
             }
-            return RunningInstance{name:program.name, entry_point:program.entry_point, methods:allm};
+            let mut globals = HashMap::<String, Box<dyn Value>>::new();
+            let mut constants = HashMap::<String, Box<dyn Value>>::new();
+            for x in program.storage.into_iter() {
+                if let Some(v) = term_values(&vec![x.value]).pop() {
+                    if x.constant {
+                        constants.insert(x.name, v);
+                    } else {
+                        globals.insert(x.name, v);
+                    }
+                }
+            }
+            return RunningInstance{name:program.name, entry_point:program.entry_point, methods:Rc::new(allm),
+                globals:Rc::new(RefCell::new(globals)), constants:Rc::new(constants),
+                calls:Rc::new(RefCell::new(Vec::new())), max_depth:program.max_depth,
+                stack:(stack_address(), program.stack_size)};
+        }
+
+        //NESTED BLOCKS ALSO USE THE NATIVE STACK, IT IS CHECKED SO A DEEP PROGRAM FAILS WITH RECURSION-LIMIT
+        //INSTEAD OF OVERFLOWING IT. A SIZE OF 0 MEANS THE STACK ISN'T KNOWN AND ISN'T CHECKED
+        pub fn check_stack(&self) -> Result<(), Error> {
+            let (base, size) = self.stack;
+            let here = stack_address();
+            let used = if base > here {base - here} else {here - base};
+            if size > 0 && used + STACK_MARGIN > size {
+                let chain = self.calls.borrow();
+                return Err(Error::new(ErrorKind::Other, Thrown{kind:"RECURSION-LIMIT".to_owned(),
+                    message:format!("Native stack exhausted after {} nested calls: {}", chain.len(), call_chain(&chain))}));
+            }
+            return Ok(());
+        }
+
+        //DEEP RECURSION FAILS WITH AN ERROR THAT CAN BE CAUGHT INSTEAD OF OVERFLOWING THE NATIVE STACK
+        pub fn enter(&self, name : &str) -> Result<(), Error> {
+            let mut calls = self.calls.borrow_mut();
+            if calls.len() >= self.max_depth {
+                let mut chain = calls.clone();
+                chain.push(name.to_owned());
+                return Err(Error::new(ErrorKind::Other, Thrown{kind:"RECURSION-LIMIT".to_owned(),
+                    message:format!("Recursion limit of {} calls exceeded: {}", self.max_depth, call_chain(&chain))}));
+            }
+            calls.push(name.to_owned());
+            return Ok(());
+        }
+
+        pub fn leave(&self) {
+            self.calls.borrow_mut().pop();
         }
     }
 
@@ -275,7 +1421,18 @@ pub mod runtime {
         pub stack : Vec<Box<dyn Value>>,
         pub variables : HashMap<String, Box<dyn Value>>,
         pub ret : Box<dyn Value>,
-        pub running : bool
+        pub running : bool,
+        pub namespace : String,
+        pub proc : String,
+        pub loops : usize,
+        pub jump : Option<Jump>
+    }
+
+    //Pending BREAK or CONTINUE, it stops the blocks like RETURN until the innermost loop takes it
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum Jump {
+        Break,
+        Continue
     }
 
     impl Clone for Context {
@@ -286,6 +1443,10 @@ pub mod runtime {
                 variables : self.variables.clone(),
                 ret : self.ret.clone(),
                 running : self.running.clone(),
+                namespace : self.namespace.clone(),
+                proc : self.proc.clone(),
+                loops : self.loops,
+                jump : self.jump,
             }
         }
     }
@@ -293,7 +1454,7 @@ pub mod runtime {
     impl<'a> Context {
         pub fn new(ins : Box<RunningInstance>, input : Vec<Box<dyn Value>>) -> Self {
             let mut c = Context{instance:ins, stack:Vec::new(), variables:HashMap::new(),
-                ret:Box::new(crate::types::ETVoid{}), running:true};
+                ret:Box::new(crate::types::ETVoid{}), running:true, namespace:String::new(), proc:String::new(), loops:0, jump:None};
             c.apply_args(input);
             return c;
         }
@@ -308,9 +1469,16 @@ pub mod runtime {
                     return Ok(r);
                 }
             } else {
-                for x in 0..self.instance.methods.len() {
-                    if self.instance.methods[x].name() == name {
-                        return Ok(self.instance.methods[x].clone());
+                //PROCS OF THE SAME IMPORTED FILE ARE FOUND WITHOUT THEIR PREFIX
+                let mut names = vec![name.to_owned()];
+                if !self.namespace.is_empty() {
+                    names.insert(0, format!("{}.{}", self.namespace, name));
+                }
+                for n in names.iter() {
+                    for x in 0..self.instance.methods.len() {
+                        if &self.instance.methods[x].name() == n {
+                            return Ok(self.instance.methods[x].clone());
+                        }
                     }
                 }
             }
@@ -320,13 +1488,33 @@ pub mod runtime {
         pub fn get_var(&self, name : &'a str) -> Result<Box<dyn Value>, Error> {
             if let Some(n) = self.variables.get(name) {
                 return Ok(n.clone());
+            } else if let Some(n) = self.instance.globals.borrow().get(name) {
+                return Ok(n.clone());
+            } else if let Some(n) = self.instance.constants.get(name) {
+                return Ok(n.clone());
             }
             return Err(Error::new(ErrorKind::InvalidInput, "Error searching variable"));
         }
 
+        //GLOBALS ARE SHARED BY EVERY PROC, CONSTANTS CAN'T BE ASSIGNED. PARAMETERS AND THE
+        //ALIASES OF ITER, FOR AND CATCH ARE LOCALS THAT HIDE THE ITEMS WITH THEIR NAME
+        pub fn set_var(&mut self, name : String, value : Box<dyn Value>) -> Result<(), Error> {
+            if self.variables.contains_key(&name) {
+                self.variables.insert(name, value);
+            } else if self.instance.constants.contains_key(&name) {
+                return Err(Error::new(ErrorKind::PermissionDenied, format!("Constant '{}' can't be reassigned", name)));
+            } else if self.instance.globals.borrow().contains_key(&name) {
+                self.instance.globals.borrow_mut().insert(name, value);
+            } else {
+                self.variables.insert(name, value);
+            }
+            return Ok(());
+        }
+
         pub fn pour(&mut self, sub : Context) {
             self.ret = sub.ret;
             self.running = sub.running;
+            self.jump = sub.jump;
             self.stack = sub.stack;
             for (i, c) in sub.variables.into_iter() {
                 if self.variables.contains_key(&i) {
@@ -357,8 +1545,17 @@ pub mod runtime {
             None
         }
         fn literal(&self) -> String;
-        fn is_literal(&self) -> bool {
-            false
+        fn reference(&self) -> Option<String> {
+            None
+        }
+        fn keyword(&self) -> Option<(String, Box<dyn Value>)> {
+            None
+        }
+        fn proc_ref(&self) -> Option<String> {
+            None
+        }
+        fn procedure(&self) -> Option<Box<dyn ProcExecution>> {
+            None
         }
         fn function(&self) -> Option<Box<dyn ProcExecution>> {
             None
@@ -369,6 +1566,9 @@ pub mod runtime {
         fn custom_type(&self) -> Option<Box<ETType>> {
             None
         }
+        fn range(&self) -> Option<Box<ETRange>> {
+            None
+        }
         fn target(&self) -> Box<dyn Value> {
             self.clone_box()
         }
@@ -392,6 +1592,25 @@ pub mod runtime {
 
     pub trait ProcExecution : CloneProc {
         fn name(&self) -> String;
+        fn params(&self) -> Vec<Param> {
+            Vec::new()
+        }
+        //Minimum and maximum number of arguments, None when any number is accepted
+        fn arity(&self) -> Option<(usize, Option<usize>)> {
+            None
+        }
+        //Number of leading arguments that are names of the variables it assigns
+        fn assigns(&self) -> usize {
+            0
+        }
+        //True when references are given without resolving, so the proc can read them again
+        fn lazy(&self) -> bool {
+            false
+        }
+        //True when the rows of the sub blocks are given together in one call instead of one call per row
+        fn joins_rows(&self) -> bool {
+            false
+        }
         fn run(&self, input : Vec<Box<dyn Value>>, context : &mut Context) -> Result<Box<dyn Value>, Error>;
     }
 
@@ -408,47 +1627,326 @@ pub mod runtime {
     }
 }
 
+pub mod import {
+
+    use std::fs::File;
+    use std::io::{Error, ErrorKind};
+    use std::path::{Path, PathBuf};
+    use crate::core::{Block, Proc, Statement, CallMode, Term};
+    use crate::core::lexer::{get_tokens, Indent};
+    use crate::core::structure::generate_blocks;
+    use crate::core::execution::header;
+    use crate::core::error::{located, Diagnostics};
+
+    pub fn read_blocks(path : &str, indent : Indent, diags : &mut Diagnostics) -> Result<Vec<Block>, Error> {
+        let source = File::open(path)?;
+        let tokens = get_tokens(Box::from(source), path, indent, diags)?;
+        return Ok(generate_blocks(tokens, diags));
+    }
+
+    //Looks for the file next to the importing one and then in every directory of EFECTA_PATH
+    pub fn resolve<'a>(name : &'a str, importer : &'a str) -> Option<PathBuf> {
+        let path = Path::new(name);
+        if path.is_absolute() {
+            return if path.is_file() {Some(path.to_path_buf())} else {None};
+        }
+        let mut dirs = vec![Path::new(importer).parent().unwrap_or(Path::new("")).to_path_buf()];
+        if let Some(v) = std::env::var_os("EFECTA_PATH") {
+            dirs.extend(std::env::split_paths(&v));
+        }
+        for d in dirs.into_iter() {
+            let f = d.join(path);
+            if f.is_file() {
+                return Some(f);
+            }
+        }
+        return None;
+    }
+
+    pub fn qualify<'a>(namespace : &'a str, name : &'a str) -> String {
+        if namespace.is_empty() {
+            return name.to_owned();
+        } else if name.is_empty() {
+            return namespace.to_owned();
+        }
+        return format!("{}.{}", namespace, name);
+    }
+
+    pub fn is_import(b : &Block) -> bool {
+        match &b.stmt {
+            Statement::Call(c) => c.mode == CallMode::Plain && c.name == "IMPORT",
+            _ => false
+        }
+    }
+
+    //IMPORT path [AS PREFIX]
+    fn import_args(b : &Block) -> Result<(String, String), Error> {
+        if let Statement::Call(c) = &b.stmt {
+            match c.args.as_slice() {
+                [Term::Word(p)] | [Term::Str(p)] => return Ok((p.clone(), String::new())),
+                [Term::Word(p), Term::Word(a), Term::Word(n)] | [Term::Str(p), Term::Word(a), Term::Word(n)] if a == "AS" => {
+                    return Ok((p.clone(), n.clone()));
+                }
+                _ => {}
+            }
+        }
+        return Err(located(Error::new(ErrorKind::InvalidData, "IMPORT must be followed by a file and optionally AS PREFIX"), &b.loc));
+    }
+
+    pub struct Imports {
+        indent : Indent,
+        stack : Vec<(PathBuf, String)>,
+        loaded : Vec<(PathBuf, String)>
+    }
+
+    impl Imports {
+        pub fn new(root : &str, indent : Indent) -> Self {
+            let canonical = std::fs::canonicalize(root).unwrap_or(PathBuf::from(root));
+            return Imports{indent:indent, stack:vec![(canonical, root.to_owned())], loaded:Vec::new()};
+        }
+
+        pub fn import(&mut self, b : &Block, namespace : &str, diags : &mut Diagnostics) -> Vec<Proc> {
+            let (name, prefix) = match import_args(b) {
+                Ok(x) => x,
+                Err(e) => {
+                    diags.push(e);
+                    return Vec::new();
+                }
+            };
+            let namespace = qualify(namespace, &prefix);
+            let file = match resolve(&name, &b.loc.file) {
+                Some(f) => f,
+                None => {
+                    diags.push(located(Error::new(ErrorKind::NotFound, format!("Imported file '{}' not found", name)), &b.loc));
+                    return Vec::new();
+                }
+            };
+            let display = file.to_string_lossy().to_string();
+            let canonical = std::fs::canonicalize(&file).unwrap_or(file);
+            if self.stack.iter().any(|s| s.0 == canonical) {
+                let mut chain : Vec<String> = self.stack.iter().map(|s| s.1.clone()).collect();
+                chain.push(display);
+                diags.push(located(Error::new(ErrorKind::InvalidData, "Import cycle: ".to_owned() + &chain.join(" -> ")), &b.loc));
+                return Vec::new();
+            }
+            if self.loaded.contains(&(canonical.clone(), namespace.clone())) {
+                return Vec::new();
+            }
+            self.loaded.push((canonical.clone(), namespace.clone()));
+            let blocks = match read_blocks(&display, self.indent, diags) {
+                Ok(x) => x,
+                Err(e) => {
+                    diags.push(located(e, &b.loc));
+                    return Vec::new();
+                }
+            };
+            self.stack.push((canonical, display));
+            let procs = self.library(blocks, &namespace, diags);
+            self.stack.pop();
+            return procs;
+        }
+
+        //Imported files just provide procs, their PROGRAM-ID and ENTER-IN are ignored
+        fn library(&mut self, blocks : Vec<Block>, namespace : &str, diags : &mut Diagnostics) -> Vec<Proc> {
+            let mut procs = Vec::<Proc>::new();
+            for b in blocks.into_iter() {
+                if let Statement::Invalid = b.stmt {
+                    continue;
+                } else if is_import(&b) {
+                    procs.extend(self.import(&b, namespace, diags));
+                    continue;
+                }
+                match header(&b) {
+                    Some(("PROC", Ok(n))) => match Proc::from(b, qualify(namespace, &n), namespace) {
+                        Ok(p) => procs.push(p),
+                        Err(e) => diags.push(e)
+                    }
+                    Some((_, Err(e))) => diags.push(e),
+                    Some(_) => {}
+                    None => diags.push(located(Error::new(ErrorKind::InvalidData, "PROC expected"), &b.loc))
+                }
+            }
+            return procs;
+        }
+    }
+}
+
 pub mod execution {
     use std::io::{Error, ErrorKind};
-    use crate::core::{ProgramInstance, Block, Proc};
-    use crate::core::runtime::{RunningInstance, ProcExecution, Context};
+    use crate::core::{ProgramInstance, Block, Proc, Param, StorageItem, Statement, CallMode, Term};
+    use crate::core::error::{located, exit_status, Diagnostics};
+    use crate::core::structure::check_tags;
+    use crate::core::import::{Imports, is_import};
+    use crate::core::runtime::{RunningInstance, ProcExecution, Context, DEFAULT_MAX_DEPTH};
     use crate::stdprocs::get_standard_procs;
     use crate::types::ETLiteral;
 
+    const HEADERS : [&str; 3] = ["PROGRAM-ID", "ENTER-IN", "PROC"];
+
+    //Header keyword of a top level block and the name that follows it
+    pub fn header(b : &Block) -> Option<(&'static str, Result<String, Error>)> {
+        if let Statement::Call(c) = &b.stmt {
+            for id in HEADERS.iter() {
+                if c.mode == CallMode::Plain && c.name == *id {
+                    return Some((id, match c.args.as_slice() {
+                        [Term::Word(n)] => Ok(n.clone()),
+                        [Term::Word(n), ..] if *id == "PROC" => Ok(n.clone()),
+                        _ => Err(located(Error::new(ErrorKind::InvalidData, id.to_string() + " must be followed just by one argument"), &b.loc))
+                    }));
+                }
+            }
+        }
+        return None;
+    }
+
+    fn is_storage(b : &Block) -> bool {
+        match &b.stmt {
+            Statement::Call(c) => c.mode == CallMode::Plain && c.name == "WORKING-STORAGE",
+            _ => false
+        }
+    }
+
+    //WORKING-STORAGE
+    //    CONSTANT NAME VALUE
+    //    GLOBAL NAME VALUE
+    fn working_storage(b : &Block, storage : &mut Vec<StorageItem>, diags : &mut Diagnostics) {
+        if let Statement::Call(c) = &b.stmt {
+            if c.args.len() > 0 {
+                diags.push(located(Error::new(ErrorKind::InvalidData, "WORKING-STORAGE doesn't take arguments"), &b.loc));
+            }
+        }
+        for x in b.subs.iter() {
+            let item = match &x.stmt {
+                Statement::Call(c) if c.mode == CallMode::Plain && (c.name == "CONSTANT" || c.name == "GLOBAL") => match c.args.as_slice() {
+                    [Term::Word(n), Term::Word(_)] | [Term::Word(n), Term::Str(_)] | [Term::Word(n), Term::Number(..)] if x.subs.is_empty() => {
+                        Some(StorageItem{name:n.clone(), value:c.args[1].clone(), constant:c.name == "CONSTANT", loc:x.loc.clone()})
+                    }
+                    _ => None
+                }
+                Statement::Invalid => continue,
+                _ => None
+            };
+            match item {
+                Some(i) => if storage.iter().any(|s| s.name == i.name) {
+                    diags.push(located(Error::new(ErrorKind::InvalidData, format!("'{}' is already declared in WORKING-STORAGE", i.name)), &x.loc));
+                } else {
+                    storage.push(i);
+                }
+                None => diags.push(located(Error::new(ErrorKind::InvalidData, "Expected CONSTANT NAME VALUE or GLOBAL NAME VALUE"), &x.loc))
+            }
+        }
+    }
+
+    impl Proc {
+        //PROC NAME [PARAM ...] [REST...]
+        pub fn from(b : Block, name : String, namespace : &str) -> Result<Self, Error> {
+            let mut params = Vec::<Param>::new();
+            if let Statement::Call(c) = &b.stmt {
+                for t in c.args.iter().skip(1) {
+                    let p = match t {
+                        Term::Word(w) => match w.strip_suffix("...") {
+                            Some(n) => Param{name:n.to_owned(), variadic:true, default:None},
+                            None => Param{name:w.clone(), variadic:false, default:None}
+                        }
+                        Term::Key(k, v) => match **v {
+                            Term::Ref(_) | Term::Proc(_) => return Err(located(Error::new(ErrorKind::InvalidData, format!("Default value of '{}' must be a literal", k)), &b.loc)),
+                            _ => Param{name:k.clone(), variadic:false, default:Some((**v).clone())}
+                        }
+                        _ => return Err(located(Error::new(ErrorKind::InvalidData, "PROC parameters must be plain names"), &b.loc))
+                    };
+                    if p.name.is_empty() || p.name == "ARGS" || params.iter().any(|x| x.name == p.name) {
+                        return Err(located(Error::new(ErrorKind::InvalidData, format!("Invalid or repeated parameter '{}' in PROC {}", p.name, name)), &b.loc));
+                    } else if params.last().map_or(false, |x| x.variadic) {
+                        return Err(located(Error::new(ErrorKind::InvalidData, "Only the last parameter of a PROC can be variadic"), &b.loc));
+                    } else if p.default.is_none() && !p.variadic && params.last().map_or(false, |x| x.default.is_some()) {
+                        return Err(located(Error::new(ErrorKind::InvalidData, format!("Parameter '{}' without default follows one with default", p.name)), &b.loc));
+                    }
+                    params.push(p);
+                }
+            }
+            return Ok(Proc{name:name, params:params, mems:b.subs, doc:b.doc, namespace:namespace.to_owned(), loc:b.loc});
+        }
+    }
+
     impl ProgramInstance {
-        pub fn from(global : Vec<Block>) -> Result<Self, Error> {
+        pub fn from(global : Vec<Block>, imports : &mut Imports, diags : &mut Diagnostics) -> Self {
             let mut name = String::new();
             let mut entry = String::new();
             let mut procs = Vec::<Proc>::new();
+            let mut storage = Vec::<StorageItem>::new();
+            let mut sections = 0;
+            let mut missing = Vec::<&str>::new();
             for b in global.into_iter() {
-                if name.is_empty() || entry.is_empty() {
-                    let id = if name.is_empty() {"PROGRAM-ID"} else {"ENTER-IN"};
-                    if b.head_is(&id) {
-                        let (x, i, b) = b.cut_head();
-                        if !b || i != 0  {
-                            return Err(Error::new(ErrorKind::InvalidData, id.to_owned() + " must be followed just by one argument"));
-                        }
-                        if name.is_empty() {
-                            name = x.data[0].clone();
-                        } else {
-                            entry = x.data[0].clone();
-                        }
-                    } else {
-                        return Err(Error::new(ErrorKind::InvalidData, id.to_owned() + " expected"));
-                    }
+                if let Statement::Invalid = b.stmt {
+                    continue;
+                }
+                let expected = if name.is_empty() && !missing.contains(&"PROGRAM-ID") {
+                    "PROGRAM-ID"
+                } else if entry.is_empty() && !missing.contains(&"ENTER-IN") {
+                    "ENTER-IN"
                 } else {
-                    if b.head_is("PROC") {
-                        let (x, i, b) = b.cut_head();
-                        if !b || i != 0  {
-                            return Err(Error::new(ErrorKind::InvalidData, "PROC must be followed just by one argument"));
+                    "PROC"
+                };
+                if is_storage(&b) {
+                    if expected != "PROC" {
+                        diags.push(located(Error::new(ErrorKind::InvalidData, expected.to_owned() + " expected"), &b.loc));
+                        missing.push(expected);
+                    }
+                    sections += 1;
+                    if sections > 1 {
+                        diags.push(located(Error::new(ErrorKind::InvalidData, "Duplicate WORKING-STORAGE section"), &b.loc));
+                    }
+                    working_storage(&b, &mut storage, diags);
+                    continue;
+                } else if is_import(&b) {
+                    if expected != "PROC" {
+                        diags.push(located(Error::new(ErrorKind::InvalidData, expected.to_owned() + " expected"), &b.loc));
+                        missing.push(expected);
+                    }
+                    procs.extend(imports.import(&b, "", diags));
+                    continue;
+                }
+                let found = header(&b);
+                if found.as_ref().map(|h| h.0) != Some(expected) {
+                    diags.push(located(Error::new(ErrorKind::InvalidData, expected.to_owned() + " expected"), &b.loc));
+                    if expected != "PROC" {
+                        missing.push(expected);
+                    }
+                }
+                if let Some((id, res)) = found {
+                    match res {
+                        Ok(n) => match id {
+                            "PROGRAM-ID" if name.is_empty() => name = n,
+                            "ENTER-IN" if entry.is_empty() => entry = n,
+                            "PROC" => match Proc::from(b, n, "") {
+                                Ok(p) => procs.push(p),
+                                Err(e) => diags.push(e)
+                            }
+                            _ => {}
                         }
-                        procs.push(Proc{name:x.data[0].clone(), mems:x.subs});
-                    } else {
-                        return Err(Error::new(ErrorKind::InvalidData, "PROC expected"));
+                        Err(e) => diags.push(e)
                     }
                 }
             }
-            return Ok(ProgramInstance{name:name, entry_point:entry, methods:procs});
+            for id in ["PROGRAM-ID", "ENTER-IN"].iter() {
+                let empty = if *id == "PROGRAM-ID" {name.is_empty()} else {entry.is_empty()};
+                if empty && !missing.contains(id) {
+                    diags.push(Error::new(ErrorKind::InvalidData, id.to_string() + " expected"));
+                }
+            }
+            let builtins : Vec<String> = get_standard_procs().iter().map(|x| x.name()).collect();
+            for (i, p) in procs.iter().enumerate() {
+                //THE STANDARD PROCS ARE FOUND FIRST, SO A USER PROC WITH THEIR NAME COULD NEVER BE CALLED
+                if builtins.contains(&p.name) {
+                    diags.push(located(Error::new(ErrorKind::AlreadyExists,
+                        format!("PROC {} has the name of a standard proc", p.name)), &p.loc));
+                } else if let Some(first) = procs[..i].iter().find(|x| x.name == p.name) {
+                    diags.push(located(Error::new(ErrorKind::AlreadyExists,
+                        format!("Duplicate PROC {}, it is already declared at {}", p.name, first.loc)), &p.loc));
+                }
+                check_tags(&p.mems, true, diags);
+            }
+            return ProgramInstance{name:name, entry_point:entry, storage:storage, methods:procs, max_depth:DEFAULT_MAX_DEPTH, stack_size:0};
         }
 
         pub fn run(self) -> Result<i32, Error> {
@@ -456,9 +1954,13 @@ pub mod execution {
                 let standard = get_standard_procs();
                 let r = RunningInstance::from(self.clone(), standard);
                 let args = ETLiteral::literal_array(&string_args(std::env::args()));
+                //AN INTEGER RETURNED BY THE ENTRY PROC IS THE EXIT STATUS
                 return match x.run(args.clone(), &mut Context::new(Box::new(r), args)) {
-                    Ok(_) => Ok(0),
-                    Err(e) => Err(e),
+                    Ok(v) => Ok(v.int().map_or(0, |n| n.0)),
+                    Err(e) => match exit_status(&e) {
+                        Some(code) => Ok(code),
+                        None => Err(e)
+                    }
                 }
             }
             return Err(Error::new(ErrorKind::NotFound, self.entry_point + " proc not found"));
@@ -481,4 +1983,268 @@ pub mod execution {
         }
         return res;
     }
-}
\ No newline at end of file
+}
+
+pub mod check {
+
+    use std::io::{Error, ErrorKind};
+    use crate::core::{ProgramInstance, Proc, Block, Statement, CallMode, Call, Term, Location};
+    use crate::core::error::{located, Diagnostics};
+    use crate::core::import::qualify;
+    use crate::core::runtime::{ProcExecution, arity_error};
+    use crate::stdprocs::get_standard_procs;
+
+    //Statements that call a proc, arguments rows of a call are not calls unless they are forced
+    fn call_sites<'a>(blocks : &'a [Block], proc_scope : bool, out : &mut Vec<(&'a Block, &'a Call)>) {
+        for b in blocks.iter() {
+            match &b.stmt {
+                Statement::Tag(..) => call_sites(&b.subs, true, out),
+                Statement::Call(c) if proc_scope || c.mode != CallMode::Plain => {
+                    out.push((b, c));
+                    call_sites(&b.subs, false, out);
+                }
+                _ => call_sites(&b.subs, false, out)
+            }
+        }
+    }
+
+    //Names read through '$NAME' or '!NAME'
+    fn reads<'a>(blocks : &'a [Block], out : &mut Vec<(&'a Location, &'a str)>) {
+        for b in blocks.iter() {
+            let terms : &[Term] = match &b.stmt {
+                Statement::Call(c) => {
+                    if c.mode == CallMode::Variable {
+                        out.push((&b.loc, &c.name));
+                    }
+                    &c.args
+                }
+                Statement::Row(t) | Statement::Tag(_, t) => t,
+                _ => &[]
+            };
+            for t in terms.iter() {
+                match t {
+                    Term::Ref(r) => out.push((&b.loc, r)),
+                    Term::Key(_, v) => if let Term::Ref(r) = &**v {
+                        out.push((&b.loc, r));
+                    }
+                    _ => {}
+                }
+            }
+            reads(&b.subs, out);
+        }
+    }
+
+    //Names of the procs taken as values through '&NAME'
+    fn proc_refs<'a>(blocks : &'a [Block], out : &mut Vec<(&'a Location, &'a str)>) {
+        for b in blocks.iter() {
+            let terms : &[Term] = match &b.stmt {
+                Statement::Call(c) => &c.args,
+                Statement::Row(t) | Statement::Tag(_, t) => t,
+                _ => &[]
+            };
+            for t in terms.iter() {
+                match t {
+                    Term::Proc(p) => out.push((&b.loc, p)),
+                    Term::Key(_, v) => if let Term::Proc(p) = &**v {
+                        out.push((&b.loc, p));
+                    }
+                    _ => {}
+                }
+            }
+            proc_refs(&b.subs, out);
+        }
+    }
+
+    //Number of values of every row produced by an argument block, None if it can't be known
+    fn row_sizes(b : &Block) -> Option<Vec<usize>> {
+        return match &b.stmt {
+            Statement::Tag(..) => Some(vec![1]),
+            Statement::Call(c) if c.mode != CallMode::Plain => {
+                if b.subs.is_empty() {Some(vec![1])} else {Some(vec![1; sub_rows(&b.subs)?.len()])}
+            }
+            Statement::Call(c) => extended(c.args.len()+1, &b.subs),
+            Statement::Row(t) => extended(t.len(), &b.subs),
+            Statement::Invalid => None
+        };
+    }
+
+    fn sub_rows(subs : &[Block]) -> Option<Vec<usize>> {
+        let mut res = Vec::new();
+        for s in subs.iter() {
+            res.extend(row_sizes(s)?);
+        }
+        return Some(res);
+    }
+
+    fn extended(local : usize, subs : &[Block]) -> Option<Vec<usize>> {
+        if subs.is_empty() {
+            return Some(vec![local]);
+        }
+        return Some(sub_rows(subs)?.into_iter().map(|n| local + n).collect());
+    }
+
+    struct Checker<'a> {
+        program : &'a ProgramInstance,
+        builtins : Vec<Box<dyn ProcExecution>>
+    }
+
+    impl<'a> Checker<'a> {
+        //Same order as Context::get_proc: the qualified name first, then builtins and user procs
+        fn resolve(&self, namespace : &str, name : &str) -> Option<Box<dyn ProcExecution>> {
+            let mut names = vec![name.to_owned()];
+            if !namespace.is_empty() {
+                names.insert(0, qualify(namespace, name));
+            }
+            for n in names.iter() {
+                if let Some(b) = self.builtins.iter().find(|b| &b.name() == n) {
+                    return Some(b.clone());
+                } else if let Some(p) = self.program.methods.iter().find(|p| &p.name == n) {
+                    return Some(Box::new(p.clone()));
+                }
+            }
+            return None;
+        }
+
+        fn is_user(&self, pr : &Box<dyn ProcExecution>) -> bool {
+            return !self.builtins.iter().any(|b| b.name() == pr.name());
+        }
+
+        //Every variable a proc can read, assignments are collected without following the control flow
+        fn variables(&self, p : &Proc, sites : &Vec<(&Block, &Call)>) -> Vec<String> {
+            let mut vars = vec!["ARGS".to_owned(), "SELF".to_owned()];
+            vars.extend(p.params.iter().map(|x| x.name.clone()));
+            vars.extend(self.program.storage.iter().map(|x| x.name.clone()));
+            for (b, c) in sites.iter() {
+                //THE ERROR CAUGHT BY TRY IS NAMED IN ITS CATCH TAG
+                for s in b.subs.iter() {
+                    if let Statement::Tag(t, terms) = &s.stmt {
+                        if t == "CATCH" {
+                            vars.extend(terms.iter().filter_map(|x| if let Term::Word(w) = x {Some(w.clone())} else {None}));
+                        }
+                    }
+                }
+                let names = self.resolve(&p.namespace, &c.name).map_or(0, |pr| pr.assigns());
+                if names == 0 {
+                    continue;
+                } else if !c.args.is_empty() {
+                    for t in c.args.iter().take(names) {
+                        if let Term::Word(w) = t {
+                            vars.push(w.clone());
+                        }
+                    }
+                    continue;
+                }
+                for s in b.subs.iter() {
+                    match &s.stmt {
+                        Statement::Row(t) => if let Some(Term::Word(w)) = t.first() {
+                            vars.push(w.clone());
+                        }
+                        Statement::Call(x) if x.mode == CallMode::Plain => vars.push(x.name.clone()),
+                        _ => {}
+                    }
+                }
+            }
+            return vars;
+        }
+
+        fn arity(&self, pr : &Box<dyn ProcExecution>, b : &Block, c : &Call, diags : &mut Diagnostics) {
+            let (min, max) = match pr.arity() {
+                Some(a) => a,
+                None => return
+            };
+            if c.mode == CallMode::Variable || c.args.iter().any(|t| if let Term::Key(..) = t {true} else {false}) {
+                return;
+            }
+            let sizes = if pr.joins_rows() && !b.subs.is_empty() {
+                sub_rows(&b.subs).map(|r| vec![c.args.len() + r.into_iter().sum::<usize>()])
+            } else {
+                extended(c.args.len(), &b.subs)
+            };
+            if let Some(sizes) = sizes {
+                if let Some(n) = sizes.into_iter().find(|n| *n < min || max.map_or(false, |m| *n > m)) {
+                    diags.push(located(arity_error(&pr.name(), min, max, n), &b.loc));
+                }
+            }
+        }
+
+        //Checks a proc and returns the user procs it calls
+        fn check_proc(&self, p : &Proc, diags : &mut Diagnostics) -> Vec<String> {
+            let mut sites = Vec::new();
+            call_sites(&p.mems, true, &mut sites);
+            let vars = self.variables(p, &sites);
+            let mut called = Vec::new();
+            for (b, c) in sites.iter() {
+                if c.mode == CallMode::Variable {
+                    continue;
+                }
+                match self.resolve(&p.namespace, &c.name) {
+                    Some(pr) => {
+                        self.arity(&pr, b, c, diags);
+                        if self.is_user(&pr) {
+                            called.push(pr.name());
+                        }
+                    }
+                    None => diags.push(located(Error::new(ErrorKind::NotFound, c.name.clone() + " proc not found"), &b.loc))
+                }
+            }
+            let mut names = Vec::new();
+            reads(&p.mems, &mut names);
+            for (loc, n) in names.into_iter() {
+                if vars.iter().any(|v| v == n) {
+                    continue;
+                }
+                match self.resolve(&p.namespace, n) {
+                    Some(pr) => if self.is_user(&pr) {
+                        called.push(pr.name());
+                    }
+                    None => diags.push(located(Error::new(ErrorKind::NotFound, format!("Undefined variable '{}'", n)), loc))
+                }
+            }
+            let mut refs = Vec::new();
+            proc_refs(&p.mems, &mut refs);
+            for (loc, n) in refs.into_iter() {
+                match self.resolve(&p.namespace, n) {
+                    Some(pr) => if self.is_user(&pr) {
+                        called.push(pr.name());
+                    }
+                    None => diags.push(located(Error::new(ErrorKind::NotFound, n.to_owned() + " proc not found"), loc))
+                }
+            }
+            return called;
+        }
+    }
+
+    //Static analysis of a parsed program, nothing is executed
+    pub fn check(program : &ProgramInstance) -> Result<Vec<Error>, Error> {
+        let mut diags = Diagnostics::new("the check failed");
+        let checker = Checker{program:program, builtins:get_standard_procs()};
+        let mut graph = Vec::<(String, Vec<String>)>::new();
+        for p in program.methods.iter() {
+            graph.push((p.name.clone(), checker.check_proc(p, &mut diags)));
+        }
+        match program.methods.iter().find(|p| p.name == program.entry_point) {
+            Some(entry) => {
+                //PROCS OF THE CHECKED FILE THAT CAN'T BE REACHED FROM THE ENTRY PROC
+                let mut seen = vec![entry.name.clone()];
+                let mut queue = seen.clone();
+                while let Some(n) = queue.pop() {
+                    for (_, called) in graph.iter().filter(|g| g.0 == n) {
+                        for c in called.iter() {
+                            if !seen.contains(c) {
+                                seen.push(c.clone());
+                                queue.push(c.clone());
+                            }
+                        }
+                    }
+                }
+                for p in program.methods.iter() {
+                    if p.loc.file == entry.loc.file && !seen.contains(&p.name) {
+                        diags.warn(located(Error::new(ErrorKind::InvalidData, format!("PROC {} is never called", p.name)), &p.loc));
+                    }
+                }
+            }
+            None => diags.push(Error::new(ErrorKind::NotFound, program.entry_point.clone() + " proc not found"))
+        }
+        return diags.check();
+    }
+}
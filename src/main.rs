@@ -1,28 +1,98 @@
 extern crate clap;
 
 use std::process::exit;
-use std::fs::File;
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches};
 use std::io::Error;
 
 mod stdprocs;
 mod types;
 mod core;
 
+//Native stack reserved for each nested proc call and for the whole program, the
+//deepest limit that can be chosen is the one that fits in the largest stack
+const CALL_STACK_SIZE : usize = 128 * 1024;
+const MIN_STACK_SIZE : usize = 8 * 1024 * 1024;
+const MAX_STACK_SIZE : usize = 1024 * 1024 * 1024;
+const MAX_DEPTH : usize = MAX_STACK_SIZE / CALL_STACK_SIZE;
+
 fn main() {
     let matches = App::new("Efecta Interpreter").version("0.1").author("Alberto Elorza")
         .about("Efecta is a simple programming language oriented to communication between
 processes, influenced by COBOl syntax, with stack based design and functional programming attributes")
-        .arg(Arg::with_name("file")
-            .short('f')
-            .long("file")
-            .help("Efecta source file (.esf)")
-            .takes_value(true)
+        .arg(file_arg())
+        .arg(indent_arg())
+        .arg(entry_arg())
+        .arg(Arg::with_name("max-depth")
+            .long("max-depth")
+            .help("Maximum number of nested proc calls (1000 by default, at most 8192)")
+            .takes_value(true))
+        .subcommand(App::new("check")
+            .about("Checks the program without running it")
+            .arg(file_arg())
+            .arg(indent_arg())
+            .arg(entry_arg())
+        )
+        .subcommand(App::new("list")
+            .about("Lists the procs of the program with their parameters and doc comments")
+            .arg(file_arg())
+            .arg(indent_arg())
         ).get_matches();
+    if let Some(m) = matches.subcommand_matches("check") {
+        match m.value_of("file") {
+            Some(n) => match load_program(n, indent_of(m), m.value_of("entry"), "the check failed").and_then(|p| core::check::check(&p)) {
+                Ok(warnings) => {
+                    for w in warnings.iter() {
+                        core::error::warning(w);
+                    }
+                    exit(0);
+                }
+                Err(r) => {
+                    core::error::report(&r);
+                    exit(core::error::FRONT_END_FAILURE);
+                }
+            },
+            None => {
+                eprintln!("ERROR!: No input file");
+                exit(1);
+            }
+        }
+    } else if let Some(m) = matches.subcommand_matches("list") {
+        match m.value_of("file") {
+            Some(n) => match load_program(n, indent_of(m), None, "the procs can't be listed") {
+                Ok(p) => {
+                    for x in p.methods.iter() {
+                        println!("{}", x);
+                    }
+                    exit(0);
+                }
+                Err(r) => {
+                    core::error::report(&r);
+                    exit(core::error::FRONT_END_FAILURE);
+                }
+            },
+            None => {
+                eprintln!("ERROR!: No input file");
+                exit(1);
+            }
+        }
+    }
+    let indent = indent_of(&matches);
     match matches.value_of("file") {
-        Some(n) => match run_program(n) {
-            Ok(x) => exit(x),
-            Err(r) => eprintln!("Error: {}", r)
+        Some(n) => match load_program(n, indent, matches.value_of("entry"), "the program will not run") {
+            Ok(mut instance) => {
+                instance.max_depth = depth_of(&matches);
+                match run_program(instance) {
+                    Ok(x) => exit(x),
+                    Err(r) => {
+                        core::error::report(&r);
+                        exit(core::error::RUNTIME_FAILURE);
+                    }
+                }
+            },
+            Err(r) => {
+                core::error::report(&r);
+                exit(core::error::FRONT_END_FAILURE);
+            }
         },
         None => {
             eprintln!("ERROR!: No input file");
@@ -31,6 +101,67 @@ processes, influenced by COBOl syntax, with stack based design and functional pr
     };
 }
 
+fn file_arg<'a>() -> Arg<'a> {
+    return Arg::with_name("file")
+        .short('f')
+        .long("file")
+        .help("Efecta source file (.esf)")
+        .takes_value(true);
+}
+
+fn indent_arg<'a>() -> Arg<'a> {
+    return Arg::with_name("indent")
+        .long("indent")
+        .help("Number of spaces used as indentation unit (tabs by default)")
+        .takes_value(true);
+}
+
+fn entry_arg<'a>() -> Arg<'a> {
+    return Arg::with_name("entry")
+        .short('e')
+        .long("entry")
+        .help("Proc where the execution starts (the ENTER-IN one by default)")
+        .takes_value(true);
+}
+
+fn indent_of(matches : &ArgMatches) -> core::lexer::Indent {
+    return match matches.value_of("indent") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(x) if x > 0 => core::lexer::Indent::Spaces(x),
+            _ => {
+                eprintln!("ERROR!: The indentation unit must be a positive number of spaces");
+                exit(1);
+            }
+        },
+        None => core::lexer::Indent::Tabs
+    };
+}
+
+fn depth_of(matches : &ArgMatches) -> usize {
+    return match matches.value_of("max-depth") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(x) if x > 0 && x <= MAX_DEPTH => x,
+            _ => {
+                eprintln!("ERROR!: The maximum call depth must be a number between 1 and {}", MAX_DEPTH);
+                exit(1);
+            }
+        },
+        None => core::runtime::DEFAULT_MAX_DEPTH
+    };
+}
+
+//EACH EFECTA CALL NESTS SEVERAL RUST FRAMES, SO THE STACK GROWS WITH THE ALLOWED DEPTH
+//AND THE INTERPRETER CHECKS IT, SO A PROGRAM THAT NESTS TOO MANY BLOCKS ALSO FAILS WITH RECURSION-LIMIT
+fn run_program(mut instance : core::ProgramInstance) -> Result<i32, Error> {
+    let depth = instance.max_depth;
+    let size = depth.saturating_mul(CALL_STACK_SIZE).max(MIN_STACK_SIZE).min(MAX_STACK_SIZE);
+    instance.stack_size = size;
+    return match std::thread::Builder::new().stack_size(size).spawn(move || instance.run()) {
+        Ok(t) => t.join().unwrap_or_else(|_| Err(Error::new(std::io::ErrorKind::Other, "The interpreter panicked"))),
+        Err(e) => Err(Error::new(e.kind(), format!("Can't reserve the stack for {} nested calls: {}", depth, e)))
+    };
+}
+
 /*fn iterblock(f : core::Block, lev : i32) {
     for _ in 0..lev {
         print!("()");
@@ -44,18 +175,26 @@ processes, influenced by COBOl syntax, with stack based design and functional pr
     }
 }*/
 
-fn run_program(src_file : &str) -> Result<i32, Error>{
+fn load_program(src_file : &str, indent : core::lexer::Indent, entry : Option<&str>, summary : &'static str) -> Result<core::ProgramInstance, Error>{
     //1st LEXER
     //2nd GENERATE BLOCK STRUCTURE
-    //3rd EXECUTE THE MAIN BLOCK
-    let source = File::open(src_file)?;
-    let tokens = core::lexer::get_tokens(Box::from(source))?;
-    let blocks = core::structure::generate_blocks(tokens)?;
+    //3rd CHECK THE PROGRAM STRUCTURE (THE MAIN BLOCK IS EXECUTED BY THE CALLER)
+    //ALL THE ERRORS OF THESE STEPS ARE REPORTED TOGETHER
+    let mut diags = core::error::Diagnostics::new(summary);
+    let blocks = core::import::read_blocks(src_file, indent, &mut diags)?;
     /*for t in blocks.clone().into_iter() {
         iterblock(t, 0);
     }*/
     use crate::core::ProgramInstance;
-    let instance = ProgramInstance::from(blocks)?;
+    let mut imports = core::import::Imports::new(src_file, indent);
+    let mut instance = ProgramInstance::from(blocks, &mut imports, &mut diags);
+    if let Some(e) = entry {
+        instance.entry_point = e.to_owned();
+    }
+    if !instance.entry_point.is_empty() && !instance.methods.iter().any(|p| p.name == instance.entry_point) {
+        diags.push(Error::new(std::io::ErrorKind::NotFound, instance.entry_point.clone() + " proc not found"));
+    }
+    diags.check()?;
     //println!("COMPILED DATA:\n{}\n{}", instance.name, instance.entry_point);
-    return instance.run();
+    return Ok(instance);
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
-use crate::core::runtime::{ProcExecution, Value, Context};
+use crate::core::runtime::{ProcExecution, Value, Context, Jump, resolve_param};
 use std::io::{Error, ErrorKind};
 use crate::types;
+use crate::core::error;
 use std::collections::HashMap;
 
 pub enum StrictType {
@@ -27,7 +28,7 @@ fn value_from_type(stype : StrictType) -> Box<dyn Value> {
         StrictType::List => Box::new(types::ETList(Vec::new())),
         StrictType::Map => Box::new(types::ETMap(HashMap::new())),
         StrictType::Literal => Box::new(types::ETString(String::new())),
-        StrictType::Block => Box::new(types::ETBlock(crate::core::Block{subs:Vec::new(), data:Vec::new()})),
+        StrictType::Block => Box::new(types::ETBlock(crate::core::Block{subs:Vec::new(), stmt:crate::core::Statement::Row(Vec::new()), loc:Default::default(), doc:Vec::new()})),
     }
 }
 
@@ -103,6 +104,10 @@ pub fn expect_char(v : &Box<dyn Value>) -> Result<Box<types::ETString>, Error> {
 }
 
 pub fn expect_float(v : &Box<dyn Value>) -> Result<Box<types::ETFloat>, Error> {
+    //INTEGERS ARE TAKEN BY VALUE, THEIR LITERAL CAN BE WRITTEN IN HEX OR BINARY
+    if let Some(n) = v.int() {
+        return Ok(Box::new(types::ETFloat(n.0 as f64)));
+    }
     if let Some(_) = assert_type(v, StrictType::Float) {
         if let Some(e) = assert_type_lit(v.literal(), LiteralParsableType::Float) {
             return Err(e);
@@ -130,6 +135,24 @@ pub fn expect_bool(v : &Box<dyn Value>) -> Result<Box<types::ETInt>, Error> {
     return Ok(v.int().unwrap());
 }
 
+//Runs one iteration of a loop, false when the loop must finish (BREAK or RETURN)
+fn iteration(b : &crate::core::Block, mut n : Context, con : &mut Context) -> Result<bool, Error> {
+    n.loops += 1;
+    for x in b.subs.iter() {
+        x.run(&mut n, true)?;
+        if !n.running {
+            break;
+        }
+    }
+    n.loops -= 1;
+    let jump = n.jump.take();
+    if jump.is_some() {
+        n.running = true;
+    }
+    con.pour(n);
+    return Ok(con.running && jump != Some(Jump::Break));
+}
+
 #[derive(Clone)]
 pub struct EPDisplay;
 impl ProcExecution for EPDisplay {
@@ -137,6 +160,10 @@ impl ProcExecution for EPDisplay {
         "DISPLAY".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(1)))
+    }
+
     fn run(&self , input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 1) {
             return Err(n);
@@ -153,11 +180,80 @@ impl ProcExecution for EPReturn {
         "RETURN".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(1)))
+    }
+
     fn run(&self , input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 1) {
             return Err(n);
         }
         c.ret = input[0].clone();
+        c.running = false;
+        return Ok(Box::new(types::ETVoid));
+    }
+}
+
+#[derive(Clone)]
+pub struct EPStop;
+impl ProcExecution for EPStop {
+    fn name(&self) -> String {
+        "STOP".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((0, Some(0)))
+    }
+
+    fn run(&self , input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if let Some(n) = assert_len(input.len(), 0) {
+            return Err(n);
+        }
+        c.running = false;
+        return Ok(Box::new(types::ETVoid));
+    }
+}
+
+#[derive(Clone)]
+pub struct EPExit;
+impl ProcExecution for EPExit {
+    fn name(&self) -> String {
+        "EXIT".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((0, Some(1)))
+    }
+
+    fn run(&self , input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
+        return match input.len() {
+            0 => Err(crate::core::error::exit(0)),
+            1 => Err(crate::core::error::exit(expect_int(&input[0])?.0)),
+            n => Err(Error::new(ErrorKind::Other, format!("Expected 1 and got {}", n)))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EPJump(pub Jump);
+impl ProcExecution for EPJump {
+    fn name(&self) -> String {
+        if self.0 == Jump::Break {"BREAK"} else {"CONTINUE"}.to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((0, Some(0)))
+    }
+
+    fn run(&self , input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if let Some(n) = assert_len(input.len(), 0) {
+            return Err(n);
+        }
+        if c.loops == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, self.name() + " used outside of a loop"));
+        }
+        c.jump = Some(self.0);
+        c.running = false;
         return Ok(Box::new(types::ETVoid));
     }
 }
@@ -169,12 +265,20 @@ impl ProcExecution for EPInt {
         "INT".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(2)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self , input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 1) {
             if input.len() == 2 {
                 let mut i = c.expect_variable(input[0].literal(), StrictType::Integer)?.int().unwrap();
                 i.0 += expect_int(&input[1])?.0;
-                c.variables.insert(input[0].literal(), i.clone());
+                c.set_var(input[0].literal(), i.clone())?;
                 return Ok(i);
             }
             return Err(n);
@@ -193,12 +297,20 @@ impl ProcExecution for EPLit {
         "LIT".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(2)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self , input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 1) {
             if input.len() == 2 {
                 let mut e = c.expect_variable(input[0].literal(), StrictType::Literal)?.stringval().unwrap();
                 e.0.push_str(&input[1].literal());
-                c.variables.insert(input[0].literal(), e.clone());
+                c.set_var(input[0].literal(), e.clone())?;
                 return Ok(e);
             }
             return Err(n);
@@ -214,12 +326,20 @@ impl ProcExecution for EPFloat {
         "FLOAT".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(2)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self , input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 1) {
             if input.len() == 2 {
                 let mut f = c.expect_variable(input[0].literal(), StrictType::Float)?.float().unwrap();
                 f.0 += expect_float(&input[1])?.0;
-                c.variables.insert(input[0].literal(), f.clone());
+                c.set_var(input[0].literal(), f.clone())?;
                 return Ok(f);
             }
             return Err(n);
@@ -238,12 +358,20 @@ impl ProcExecution for EPLst {
         "LST".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(2)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 1) {
             if input.len() == 2 {
                 let mut list = c.expect_variable(input[0].literal(), StrictType::List)?.list().unwrap();
                 list.add(input[1].clone());
-                c.variables.insert(input[0].literal(), list.clone());
+                c.set_var(input[0].literal(), list.clone())?;
                 return Ok(list);
             }
             return Err(n);
@@ -259,12 +387,20 @@ impl ProcExecution for EPMap {
         "MAP".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(3)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 2) {
             if input.len() == 3 {
                 let mut map = c.expect_variable(input[0].literal(), StrictType::Map)?.map().unwrap();
                 map.add(input[1].literal(), input[2].clone());
-                c.variables.insert(input[0].literal(), map.clone());
+                c.set_var(input[0].literal(), map.clone())?;
                 return Ok(map);
             }
             return Err(n);
@@ -280,6 +416,10 @@ impl ProcExecution for EPGet {
         "GET".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(n) = assert_len(input.len(), 2) {
             return Err(n);
@@ -303,6 +443,10 @@ impl ProcExecution for EPOp {
         self.0.to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 2) {
             return Err(e);
@@ -327,6 +471,10 @@ impl ProcExecution for EPIf {
         "IF".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 2) {
             return Err(e);
@@ -336,9 +484,9 @@ impl ProcExecution for EPIf {
             return Err(e);
         }
         let b : crate::core::Block = input[1].block().unwrap().0;
-        match &b.data[0][..] {
-            "THEN" | "ELSE" => {
-                if c ^ (b.data[0] == "ELSE") {
+        match b.tag() {
+            Some("THEN") | Some("ELSE") => {
+                if c ^ (b.tag() == Some("ELSE")) {
                     let mut n = con.clone();
                     b.run_named(&mut n)?;
                     con.pour(n);
@@ -350,6 +498,208 @@ impl ProcExecution for EPIf {
     }
 }
 
+//Equality of two values, numbers are compared by value
+fn same_value(a : &Box<dyn Value>, b : &Box<dyn Value>) -> bool {
+    return match (expect_float(a), expect_float(b)) {
+        (Ok(x), Ok(y)) => x.0 == y.0,
+        _ => a.literal() == b.literal()
+    };
+}
+
+//Numbers are compared by value and anything else by its literal
+fn compare_values(a : &Box<dyn Value>, b : &Box<dyn Value>) -> Option<std::cmp::Ordering> {
+    return match (expect_float(a), expect_float(b)) {
+        (Ok(x), Ok(y)) => x.0.partial_cmp(&y.0),
+        _ => Some(a.literal().cmp(&b.literal()))
+    };
+}
+
+#[derive(Clone)]
+pub struct EPEvaluate;
+impl EPEvaluate {
+    //:WHEN A B THRU C $PREDICATE
+    fn matches(&self, subject : &Box<dyn Value>, b : &crate::core::Block, con : &mut Context) -> Result<bool, Error> {
+        let args = b.tag_args();
+        if args.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "WHEN expects at least one value"));
+        }
+        let mut i = 0;
+        while i < args.len() {
+            let found = if let Some(name) = args[i].reference() {
+                match con.get_var(&name) {
+                    Ok(v) => same_value(subject, &v),
+                    Err(_) => {
+                        let pr = con.get_proc(false, &name)?;
+                        expect_bool(&pr.run(vec![subject.clone()], con)?)?.0 != 0
+                    }
+                }
+            } else if args.get(i+1).map_or(false, |x| x.literal() == "THRU") {
+                let (low, high) = match args.get(i+2) {
+                    Some(h) => (resolve_param(con, args[i].clone())?, resolve_param(con, h.clone())?),
+                    None => return Err(Error::new(ErrorKind::InvalidData, "THRU expects the end of the range"))
+                };
+                i += 2;
+                compare_values(&low, subject) != Some(std::cmp::Ordering::Greater) &&
+                    compare_values(subject, &high) != Some(std::cmp::Ordering::Greater)
+            } else {
+                same_value(subject, &args[i])
+            };
+            if found {
+                return Ok(true);
+            }
+            i += 1;
+        }
+        return Ok(false);
+    }
+}
+impl ProcExecution for EPEvaluate {
+    fn name(&self) -> String {
+        "EVALUATE".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, None))
+    }
+
+    fn joins_rows(&self) -> bool {
+        true
+    }
+
+    fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if input.len() < 2 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Expected a value and its branches"));
+        }
+        let mut other = None;
+        let mut chosen = None;
+        for v in input[1..].iter() {
+            if let Some(e) = assert_type(v, StrictType::Block) {
+                return Err(e);
+            }
+            let b : crate::core::Block = v.block().unwrap().0;
+            match b.tag() {
+                Some("WHEN") => if chosen.is_none() && self.matches(&input[0], &b, con)? {
+                    chosen = Some(b);
+                }
+                Some("OTHER") if other.is_none() => other = Some(b),
+                Some("OTHER") => return Err(Error::new(ErrorKind::InvalidData, "Only one OTHER branch is allowed")),
+                _ => return Err(Error::new(ErrorKind::InvalidData, "Wrong behaviour tag"))
+            }
+        }
+        if let Some(b) = chosen.or(other) {
+            let mut n = con.clone();
+            b.run_named(&mut n)?;
+            con.pour(n);
+        }
+        return Ok(Box::new(types::ETVoid{}));
+    }
+}
+
+//Map given to the CATCH block: KIND, MESSAGE, PROC and LOCATION of the error, the
+//proc is the running one when the error was raised directly inside the TRY
+fn error_value(e : &Error, con : &Context) -> Box<dyn Value> {
+    let (kind, message) = error::kind_and_message(e);
+    let mut map = types::ETMap::new("KIND".to_owned(), Box::new(types::ETString(kind)));
+    map.add("MESSAGE".to_owned(), Box::new(types::ETString(message)));
+    let proc = error::source_error(e).map_or(String::new(), |s| s.proc.clone());
+    map.add("PROC".to_owned(), Box::new(types::ETString(if proc.is_empty() {con.proc.clone()} else {proc})));
+    map.add("LOCATION".to_owned(), Box::new(types::ETString(error::location(e).map_or(String::new(), |l| l.to_string()))));
+    return Box::new(map);
+}
+
+#[derive(Clone)]
+pub struct EPTry;
+impl ProcExecution for EPTry {
+    fn name(&self) -> String {
+        "TRY".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, None))
+    }
+
+    fn joins_rows(&self) -> bool {
+        true
+    }
+
+    fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
+        let (mut body, mut catch, mut finally) = (None, None, None);
+        for v in input.iter() {
+            if let Some(e) = assert_type(v, StrictType::Block) {
+                return Err(e);
+            }
+            let b : crate::core::Block = v.block().unwrap().0;
+            let branch = match b.tag() {
+                Some("DO") => &mut body,
+                Some("CATCH") => &mut catch,
+                Some("FINALLY") => &mut finally,
+                _ => return Err(Error::new(ErrorKind::InvalidData, "Wrong behaviour tag"))
+            };
+            if branch.is_some() {
+                return Err(Error::new(ErrorKind::InvalidData, "Every TRY branch can be given just once"));
+            }
+            *branch = Some(b);
+        }
+        let body = match body {
+            Some(b) => b,
+            None => return Err(Error::new(ErrorKind::InvalidData, "TRY expects a DO block"))
+        };
+        let mut n = con.clone();
+        let mut res = body.run_named(&mut n).map(|_| ());
+        con.pour(n);
+        if let Some(c) = catch {
+            //EXIT CAN'T BE CAUGHT
+            res = match res {
+                Err(e) if error::exit_status(&e).is_none() => {
+                    let mut n = con.clone();
+                    if let Some(alias) = c.tag_args().first() {
+                        n.variables.insert(alias.literal(), error_value(&e, con));
+                    }
+                    let r = c.run_named(&mut n).map(|_| ());
+                    con.pour(n);
+                    r
+                }
+                r => r
+            };
+        }
+        //EXIT ENDS THE PROGRAM RIGHT AWAY, FINALLY ISN'T RUN FOR IT
+        let exiting = res.as_ref().err().map_or(false, |e| error::exit_status(e).is_some());
+        if let (Some(f), false) = (finally, exiting) {
+            //FINALLY ALSO RUNS AFTER RETURN, BREAK OR CONTINUE, THEY GO ON AFTER IT
+            let (running, jump) = (con.running, con.jump);
+            let mut n = con.clone();
+            n.running = true;
+            n.jump = None;
+            f.run_named(&mut n)?;
+            con.pour(n);
+            if con.running {
+                con.running = running;
+                con.jump = jump;
+            }
+        }
+        res?;
+        return Ok(Box::new(types::ETVoid{}));
+    }
+}
+
+#[derive(Clone)]
+pub struct EPThrow;
+impl ProcExecution for EPThrow {
+    fn name(&self) -> String {
+        "THROW".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
+    fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if let Some(e) = assert_len(input.len(), 2) {
+            return Err(e);
+        }
+        return Err(Error::new(ErrorKind::Other, error::Thrown{kind:input[0].literal(), message:input[1].literal()}));
+    }
+}
+
 #[derive(Clone)]
 pub struct EPTer;
 impl ProcExecution for EPTer {
@@ -357,6 +707,10 @@ impl ProcExecution for EPTer {
         "TER".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((3, Some(3)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 3) {
             return Err(e);
@@ -373,6 +727,10 @@ impl ProcExecution for EPPush {
         "PUSH".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(1)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 1) {
             return Err(e);
@@ -389,6 +747,10 @@ impl ProcExecution for EPRecv {
         "RECV".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((0, Some(0)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 0) {
             return Err(e);
@@ -409,10 +771,18 @@ impl ProcExecution for EPLen {
         "LEN".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(1)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 1) {
             return Err(e);
         }
+        //RANGES ARE MEASURED WITHOUT MAKING THE LIST
+        if let Some(r) = input[0].range() {
+            return Ok(Box::new(types::ETInt(r.len().min(i32::MAX as usize) as i32)));
+        }
         if let Some(e) = assert_type(&input[0], StrictType::List) {
             return Err(e);
         }
@@ -427,6 +797,10 @@ impl ProcExecution for EPInput {
         "INPUT".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((0, Some(0)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 0) {
             return Err(e);
@@ -444,6 +818,10 @@ impl ProcExecution for EPCon {
         if self.0 {"JOIN"} else {"CON"}.to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((if self.0 {1} else {0}, None))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         let mut x = String::new();
         if self.0 {
@@ -473,6 +851,10 @@ impl ProcExecution for EPCharnum {
         if self.0 {"CHR"} else {"ORD"}.to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, Some(1)))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if self.0 {
             Ok(Box::new(types::ETString(std::char::from_u32(expect_int(&input[0])?.0 as u32).unwrap().to_string())))
@@ -482,6 +864,31 @@ impl ProcExecution for EPCharnum {
     }
 }
 
+type Items = Box<dyn Iterator<Item = (Box<dyn Value>, Option<Box<dyn Value>>)>>;
+
+//Elements walked by ITER, maps give their keys in order along with the values
+fn iter_items(v : &Box<dyn Value>) -> Result<Items, Error> {
+    if let Some(mut t) = v.custom_type() {
+        if t.has("ITER") {
+            return iter_items(&t.inv("ITER", Vec::new())?);
+        }
+    }
+    //RANGES ARE WALKED WITHOUT MAKING THE LIST
+    if let Some(r) = v.range() {
+        return Ok(Box::new(r.values().map(|x| (x, None))));
+    } else if let Some(m) = v.map() {
+        let mut entries : Vec<(String, Box<dyn Value>)> = m.0.into_iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        return Ok(Box::new(entries.into_iter().map(|(k, x)| (Box::new(types::ETString(k)) as Box<dyn Value>, Some(x)))));
+    } else if let Some(l) = v.list() {
+        return Ok(Box::new(l.0.into_iter().map(|x| (x, None))));
+    } else if let Some(s) = v.stringval() {
+        let chars : Vec<char> = s.0.chars().collect();
+        return Ok(Box::new(chars.into_iter().map(|c| (Box::new(types::ETString(c.to_string())) as Box<dyn Value>, None))));
+    }
+    return Err(Error::new(ErrorKind::InvalidInput, "Expected list, map, range, string or a type with ITER"));
+}
+
 #[derive(Clone)]
 pub struct EPIter;
 impl ProcExecution for EPIter {
@@ -489,29 +896,42 @@ impl ProcExecution for EPIter {
         "ITER".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((3, Some(4)))
+    }
+
+    fn assigns(&self) -> usize {
+        2
+    }
+
+    //ITER ALIAS COLLECTION :THEN or ITER KEY VALUE MAP :THEN
     fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
-        if let Some(e) = assert_len(input.len(), 3) {
-            return Err(e);
+        if input.len() < 3 || input.len() > 4 {
+            return Err(Error::new(ErrorKind::Other, format!("Expected 3 or 4 and got {}", input.len())));
         }
         let alias = input[0].literal();
-        if let Some(e) = assert_type(&input[1], StrictType::List) {
-            return Err(e);
+        let second = if input.len() == 4 {Some(input[1].literal())} else {None};
+        let (collection, block) = (&input[input.len()-2], &input[input.len()-1]);
+        if second.is_some() && collection.map().is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Two aliases can only be used with maps"));
         }
-        let list = input[1].list().unwrap();
-        if let Some(e) = assert_type(&input[2], StrictType::Block) {
+        let items = iter_items(collection)?;
+        if let Some(e) = assert_type(block, StrictType::Block) {
             return Err(e);
         }
-        let b : crate::core::Block = input[2].block().unwrap().0;
-        match &b.data[0][..] {
-            "THEN" => {
-                for (i, v) in list.0.into_iter().enumerate() {
+        let b : crate::core::Block = block.block().unwrap().0;
+        match b.tag() {
+            Some("THEN") => {
+                for (i, (v, value)) in items.enumerate() {
                     let mut n = con.clone();
                     n.variables.insert(alias.clone(), Box::new(types::ETAlias(v,
                         Box::new(types::ETMap::new("IDX".to_owned(), Box::new(types::ETInt(i as i32)))))));
-                    for x in b.subs.iter() {
-                        x.run(&mut n, true)?;
+                    if let (Some(s), Some(x)) = (&second, value) {
+                        n.variables.insert(s.clone(), x);
+                    }
+                    if !iteration(&b, n, con)? {
+                        break;
                     }
-                    con.pour(n);
                 }
             }
             _ => return Err(Error::new(ErrorKind::InvalidData, "Wrong behaviour tag"))
@@ -520,6 +940,203 @@ impl ProcExecution for EPIter {
     }
 }
 
+//Range between two numbers, it has integers unless one of the bounds or the step is a float
+fn range_of(start : &Box<dyn Value>, end : &Box<dyn Value>, step : Option<&Box<dyn Value>>) -> Result<Box<types::ETRange>, Error> {
+    let mut bounds = vec![start, end];
+    bounds.extend(step);
+    let integer = bounds.iter().all(|v| v.float().is_none() && expect_int(v).is_ok());
+    let mut n = Vec::new();
+    for v in bounds.iter() {
+        n.push(expect_float(v)?.0);
+    }
+    return Ok(Box::new(types::ETRange::new(n[0], n[1], *n.get(2).unwrap_or(&1.0), integer)?));
+}
+
+//Calls the proc carried by the value, any other value is called as '$NAME ARGS' would do
+fn call_value(f : &Box<dyn Value>, mut args : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
+    if let Some(p) = f.procedure() {
+        return p.run(args, con);
+    }
+    return match f.function() {
+        Some(p) => {
+            args.insert(0, f.target());
+            p.run(args, con)
+        }
+        None => Err(Error::new(ErrorKind::InvalidInput, "Proc reference expected"))
+    };
+}
+
+fn truth(b : bool) -> Box<dyn Value> {
+    return Box::new(types::ETLiteral((if b {"True"} else {"False"}).to_owned()));
+}
+
+#[derive(Clone)]
+pub struct EPApply(pub String);
+impl ProcExecution for EPApply {
+    fn name(&self) -> String {
+        self.0.to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        if self.0 == "REDUCE" {Some((2, Some(3)))} else {Some((2, Some(2)))}
+    }
+
+    //NAME &PROC COLLECTION, REDUCE ALSO TAKES THE INITIAL VALUE
+    fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if input.len() != 3 || self.0 != "REDUCE" {
+            if let Some(e) = assert_len(input.len(), 2) {
+                return Err(e);
+            }
+        }
+        let f = &input[0];
+        let mut items = iter_items(&input[1])?.map(|(v, _)| v);
+        let s : &str = &self.0;
+        return match s {
+            "MAPL" => {
+                let mut res = Vec::new();
+                for v in items {
+                    res.push(call_value(f, vec![v], con)?);
+                }
+                Ok(Box::new(types::ETList(res)))
+            }
+            "FILTER" => {
+                let mut res = Vec::new();
+                for v in items {
+                    if expect_bool(&call_value(f, vec![v.clone()], con)?)?.0 != 0 {
+                        res.push(v);
+                    }
+                }
+                Ok(Box::new(types::ETList(res)))
+            }
+            "ANY" | "ALL" => {
+                //STOPS AT THE FIRST VALUE THAT DECIDES THE RESULT
+                let all = s == "ALL";
+                for v in items {
+                    if (expect_bool(&call_value(f, vec![v], con)?)?.0 != 0) != all {
+                        return Ok(truth(!all));
+                    }
+                }
+                Ok(truth(all))
+            }
+            "SORT-BY" => {
+                let mut keyed = Vec::new();
+                for v in items {
+                    keyed.push((call_value(f, vec![v.clone()], con)?, v));
+                }
+                keyed.sort_by(|a, b| compare_values(&a.0, &b.0).unwrap_or(std::cmp::Ordering::Equal));
+                Ok(Box::new(types::ETList(keyed.into_iter().map(|(_, v)| v).collect())))
+            }
+            "REDUCE" => {
+                let mut acc = match input.get(2).cloned().or_else(|| items.next()) {
+                    Some(v) => v,
+                    None => return Err(Error::new(ErrorKind::InvalidInput, "REDUCE of an empty collection needs an initial value"))
+                };
+                for v in items {
+                    acc = call_value(f, vec![acc, v], con)?;
+                }
+                Ok(acc)
+            }
+            _ => Err(Error::new(ErrorKind::NotFound, "Operation not found"))
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct EPRange;
+impl ProcExecution for EPRange {
+    fn name(&self) -> String {
+        "RANGE".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(3)))
+    }
+
+    fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if input.len() < 2 || input.len() > 3 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Expected RANGE START END [STEP]"));
+        }
+        return Ok(range_of(&input[0], &input[1], input.get(2))?);
+    }
+}
+
+#[derive(Clone)]
+pub struct EPFor;
+impl ProcExecution for EPFor {
+    fn name(&self) -> String {
+        "FOR".to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((6, Some(8)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
+    //FOR NAME FROM START TO END [BY STEP]
+    fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
+        let word = |v : &Box<dyn Value>, w : &str| v.literal() == w;
+        let (alias, range, block) = match input.as_slice() {
+            [i, f, a, t, b, x] if word(f, "FROM") && word(t, "TO") => (i, range_of(a, b, None)?, x),
+            [i, f, a, t, b, y, s, x] if word(f, "FROM") && word(t, "TO") && word(y, "BY") => (i, range_of(a, b, Some(s))?, x),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "Expected FOR NAME FROM START TO END [BY STEP]"))
+        };
+        if let Some(e) = assert_type(block, StrictType::Block) {
+            return Err(e);
+        }
+        let b : crate::core::Block = block.block().unwrap().0;
+        if b.tag() != Some("DO") {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong behaviour tag"));
+        }
+        for v in range.values() {
+            let mut n = con.clone();
+            n.variables.insert(alias.literal(), v);
+            if !iteration(&b, n, con)? {
+                break;
+            }
+        }
+        return Ok(Box::new(types::ETVoid{}));
+    }
+}
+
+#[derive(Clone)]
+pub struct EPWhile(pub bool);
+impl ProcExecution for EPWhile {
+    fn name(&self) -> String {
+        if self.0 {"WHILE"} else {"UNTIL"}.to_owned()
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
+    fn lazy(&self) -> bool {
+        true
+    }
+
+    fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
+        if let Some(e) = assert_len(input.len(), 2) {
+            return Err(e);
+        }
+        if let Some(e) = assert_type(&input[1], StrictType::Block) {
+            return Err(e);
+        }
+        let b : crate::core::Block = input[1].block().unwrap().0;
+        if b.tag() != Some("DO") {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong behaviour tag"));
+        }
+        //THE CONDITION ('$NAME' OF A VARIABLE OR A PROC) IS READ AGAIN BEFORE EVERY ITERATION
+        while (expect_bool(&resolve_param(con, input[0].clone())?)?.0 != 0) == self.0 {
+            if !iteration(&b, con.clone(), con)? {
+                break;
+            }
+        }
+        return Ok(Box::new(types::ETVoid{}));
+    }
+}
+
 #[derive(Clone)]
 pub struct EPType;
 impl ProcExecution for EPType {
@@ -527,6 +1144,14 @@ impl ProcExecution for EPType {
         "TYPE".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, con : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 2) {
             return Err(e);
@@ -542,7 +1167,7 @@ impl ProcExecution for EPType {
             return Err(e);
         }
         custom.add(input[1].clone().block().unwrap().0, con.clone());
-        con.variables.insert(input[0].literal(), custom);
+        con.set_var(input[0].literal(), custom)?;
         return Ok(Box::new(types::ETVoid{}))
     }
 }
@@ -554,6 +1179,10 @@ impl ProcExecution for EPInv {
         "INV".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, None))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if input.len() < 2 {
             return Err(Error::new(ErrorKind::InvalidData, "Expected at least two argument"));
@@ -572,6 +1201,10 @@ impl ProcExecution for EPNew {
         "NEW".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((1, None))
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, _ : &mut Context) -> Result<Box<dyn Value>, Error> {
         if input.len() < 1 {
             return Err(Error::new(ErrorKind::InvalidData, "Expected at least one argument"));
@@ -592,11 +1225,19 @@ impl ProcExecution for EPSave {
         "SAVE".to_owned()
     }
 
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        Some((2, Some(2)))
+    }
+
+    fn assigns(&self) -> usize {
+        1
+    }
+
     fn run(&self, input : Vec<Box<dyn Value>>, c : &mut Context) -> Result<Box<dyn Value>, Error> {
         if let Some(e) = assert_len(input.len(), 2) {
             return Err(e);
         }
-        c.variables.insert(input[0].literal(), input[1].clone());
+        c.set_var(input[0].literal(), input[1].clone())?;
         return Ok(input[1].clone());
     }
 }
@@ -605,6 +1246,10 @@ pub fn get_standard_procs() -> Vec<Box<dyn ProcExecution>> {
     return vec![
         Box::new(EPDisplay{}),
         Box::new(EPReturn{}),
+        Box::new(EPStop{}),
+        Box::new(EPJump(Jump::Break)),
+        Box::new(EPJump(Jump::Continue)),
+        Box::new(EPExit{}),
         Box::new(EPInt{}),
         Box::new(EPLit{}),
         Box::new(EPFloat{}),
@@ -617,6 +1262,9 @@ pub fn get_standard_procs() -> Vec<Box<dyn ProcExecution>> {
         Box::new(EPOp("DIV".to_owned())),
         Box::new(EPIf{}),
         Box::new(EPTer{}),
+        Box::new(EPEvaluate{}),
+        Box::new(EPTry{}),
+        Box::new(EPThrow{}),
         Box::new(EPPush{}),
         Box::new(EPRecv{}),
         Box::new(EPLen{}),
@@ -626,6 +1274,16 @@ pub fn get_standard_procs() -> Vec<Box<dyn ProcExecution>> {
         Box::new(EPCharnum(true)),
         Box::new(EPCharnum(false)),
         Box::new(EPIter{}),
+        Box::new(EPRange{}),
+        Box::new(EPFor{}),
+        Box::new(EPWhile(true)),
+        Box::new(EPWhile(false)),
+        Box::new(EPApply("MAPL".to_owned())),
+        Box::new(EPApply("FILTER".to_owned())),
+        Box::new(EPApply("REDUCE".to_owned())),
+        Box::new(EPApply("ANY".to_owned())),
+        Box::new(EPApply("ALL".to_owned())),
+        Box::new(EPApply("SORT-BY".to_owned())),
         Box::new(EPType{}),
         Box::new(EPInv{}),
         Box::new(EPNew{}),
@@ -1,5 +1,6 @@
 use crate::core::runtime::{Value, ProcExecution, Context};
 use std::io::{Error, ErrorKind};
+use crate::core::Number;
 use crate::stdprocs as procs;
 use std::collections::HashMap;
 
@@ -49,6 +50,26 @@ impl ETFloat {
     }
 }
 
+#[derive(Clone)]
+pub struct ETNumber(pub Number, pub String); //Number written in the source, its literal is kept as it was written
+impl Value for ETNumber {
+    fn int(&self) -> Option<Box<ETInt>> {
+        return match self.0 {
+            Number::Int(n) => Some(Box::new(ETInt(n))),
+            Number::Float(_) => None
+        };
+    }
+    fn float(&self) -> Option<Box<ETFloat>> {
+        return match self.0 {
+            Number::Float(n) => Some(Box::new(ETFloat(n))),
+            Number::Int(_) => None
+        };
+    }
+    fn literal(&self) -> String {
+        return self.1.clone();
+    }
+}
+
 #[derive(Clone)]
 pub struct ETList(pub Vec<Box<dyn Value>>);
 impl Value for ETList {
@@ -142,13 +163,13 @@ impl Value for ETString {
 #[derive(Clone)]
 pub struct ETLiteral(pub String); //Literal Value(Always typed by the user)
 impl Value for ETLiteral {
-    fn is_literal(&self) -> bool {
-        true
-    }
-
     fn literal(&self) -> String {
         return self.0.clone();
     }
+
+    fn stringval(&self) -> Option<Box<ETString>> {
+        return Some(Box::new(ETString(self.0.clone())));
+    }
 }
 impl ETLiteral {
     pub fn literal_array<'a>(data : &'a Vec<String>) -> Vec<Box<dyn Value>> {
@@ -160,6 +181,110 @@ impl ETLiteral {
     }
 }
 
+#[derive(Clone)]
+pub struct ETRef(pub String); //Variable or proc name, resolved when the call that receives it runs
+impl Value for ETRef {
+    fn literal(&self) -> String {
+        return self.0.clone();
+    }
+
+    fn reference(&self) -> Option<String> {
+        return Some(self.0.clone());
+    }
+}
+
+#[derive(Clone)]
+pub struct ETKeyword(pub String, pub Box<dyn Value>); //'name=value' argument, placed by the callee's declared parameters
+impl Value for ETKeyword {
+    fn literal(&self) -> String {
+        return format!("{}={}", self.0, self.1.literal());
+    }
+
+    fn keyword(&self) -> Option<(String, Box<dyn Value>)> {
+        return Some((self.0.clone(), self.1.clone()));
+    }
+}
+
+#[derive(Clone)]
+pub struct ETProcRef(pub String); //'&NAME', it becomes an ETProc when the call that receives it runs
+impl Value for ETProcRef {
+    fn literal(&self) -> String {
+        return format!("&{}", self.0);
+    }
+
+    fn proc_ref(&self) -> Option<String> {
+        return Some(self.0.clone());
+    }
+}
+
+#[derive(Clone)]
+pub struct ETProc(pub Box<dyn ProcExecution>); //User or standard proc used as a value
+impl Value for ETProc {
+    fn literal(&self) -> String {
+        return format!("&{}", self.0.name());
+    }
+
+    fn function(&self) -> Option<Box<dyn ProcExecution>> {
+        return Some(self.0.clone());
+    }
+
+    fn procedure(&self) -> Option<Box<dyn ProcExecution>> {
+        return Some(self.0.clone());
+    }
+}
+
+//Longest range that can be turned into a list, longer ones can only be walked
+pub const MAX_RANGE_LIST : usize = 1 << 24;
+
+#[derive(Clone, Copy)]
+pub struct ETRange { //Inclusive, the values are only made when they are needed
+    pub start : f64,
+    pub end : f64,
+    pub step : f64,
+    pub integer : bool
+}
+impl Value for ETRange {
+    fn list(&self) -> Option<Box<ETList>> {
+        if self.len() > MAX_RANGE_LIST {
+            return None;
+        }
+        return Some(Box::new(ETList(self.values().collect())));
+    }
+    fn literal(&self) -> String {
+        return format!("RANGE {} {} {}", self.start, self.end, self.step);
+    }
+    fn range(&self) -> Option<Box<ETRange>> {
+        return Some(Box::new(*self));
+    }
+}
+impl ETRange {
+    pub fn new(start : f64, end : f64, step : f64, integer : bool) -> Result<Self, Error> {
+        if step == 0.0 || !step.is_finite() {
+            return Err(Error::new(ErrorKind::InvalidInput, "The step of a range can't be zero"));
+        }
+        return Ok(ETRange{start:start, end:end, step:step, integer:integer});
+    }
+
+    pub fn len(&self) -> usize {
+        let span = (self.end - self.start) / self.step;
+        if span < 0.0 || !span.is_finite() {
+            return 0;
+        }
+        //THE TOLERANCE KEEPS THE END OF FLOAT RANGES LIKE 0 1 0.1, HUGE SPANS SATURATE
+        return ((span + 1e-9).floor() as usize).saturating_add(1);
+    }
+
+    pub fn values(self) -> impl Iterator<Item = Box<dyn Value>> {
+        return (0..self.len()).map(move |i| {
+            if self.integer {
+                Box::new(ETInt((self.start as i64 + self.step as i64 * i as i64) as i32)) as Box<dyn Value>
+            } else {
+                Box::new(ETFloat(self.start + self.step * i as f64))
+            }
+        });
+    }
+}
+
 #[derive(Clone)]
 pub struct ETBlock(pub crate::core::Block);
 impl Value for ETBlock {
@@ -202,6 +327,9 @@ impl Value for ETAlias {
     fn custom_type(&self) -> Option<Box<ETType>> {
         self.0.custom_type()
     }
+    fn range(&self) -> Option<Box<ETRange>> {
+        self.0.range()
+    }
     fn target(&self) -> Box<dyn Value> {
         self.1.clone_box()
     }
@@ -265,9 +393,13 @@ impl Value for ETType {
 impl ETType {
     fn get<'a>(&self, name : &'a str) -> Option<Box<dyn Value>> {
         return match self.methods.get(name) {
-            Some(b) => match b.0.run_named(&mut b.clone().1) {
-                Ok(b) => Some(b),
-                Err(_) => None
+            Some(b) => {
+                let mut c = b.1.clone();
+                match b.0.run_named(&mut c) {
+                    //AS IN INV, AN EXPLICIT RETURN GIVES ITS VALUE
+                    Ok(v) => Some(if c.running {v} else {c.ret}),
+                    Err(_) => None
+                }
             }
             None => None
         }
@@ -280,8 +412,11 @@ impl ETType {
                 c.apply_args(params);
                 match b.0.run_named(&mut c) {
                     Ok(b) => {
+                        //A METHOD THAT RETURNS EXPLICITLY GIVES ITS RETURN VALUE INSTEAD OF THE LAST ONE
+                        let r = if c.running {b} else {c.ret.clone()};
                         self.selfc.pour(c);
-                        Ok(b)
+                        self.selfc.running = true;
+                        Ok(r)
                     }
                     Err(e) => Err(e)
                 }
@@ -298,12 +433,16 @@ impl ETType {
         return c;
     }
 
+    pub fn has<'a>(&self, name : &'a str) -> bool {
+        return self.methods.contains_key(name);
+    }
+
     pub fn void(base : Context) -> Self {
         return ETType{methods:HashMap::new(), selfc:base}
     }
 
     pub fn add(&mut self, b : crate::core::Block, c : Context) {
-        self.methods.insert(b.data[0].clone(), (b, c));
+        self.methods.insert(b.tag().unwrap_or("").to_owned(), (b, c));
     }
 
     pub fn apply_args(&mut self, args : Vec<Box<dyn Value>>) {